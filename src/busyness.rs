@@ -0,0 +1,55 @@
+// how busy the sky around the observer is, as a 0-100 score for comparing times of day.
+//
+// Each plane closer than the radius R counts (1 - d / R) ^ POWER, so a plane overhead counts
+// as a whole one and a plane at the edge counts for nothing; POWER 0 counts every plane
+// equally, and larger powers favour the close ones more. The weighted count C is squashed
+// into 100 * (1 - e ^ (-C / SATURATION)), which reaches 63 at SATURATION planes' worth and
+// approaches 100 without ever getting there.
+
+// the weighted plane count at which the score reaches 63
+pub(crate) const BUSYNESS_SATURATION: f64 = 10.0;
+// the default radius, when neither --busyness-radius nor --radius is given
+pub(crate) const DEFAULT_BUSYNESS_RADIUS_KM: f64 = 50.0;
+// the default POWER: weight falls off linearly with distance
+pub(crate) const DEFAULT_BUSYNESS_POWER: f64 = 1.0;
+
+// the score for planes at these distances, with the radius in the same units
+pub(crate) fn busyness_score(distances: &[f64], radius: f64, power: f64) -> f64 {
+    let weighted: f64 = distances
+        .iter()
+        .filter(|&&d| d < radius)
+        .map(|&d| (1.0 - d / radius).powf(power))
+        .sum();
+    100.0 * (1.0 - (-weighted / BUSYNESS_SATURATION).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busyness_score() {
+        assert_eq!(busyness_score(&[], 50.0, 1.0), 0.0);
+        // planes outside the radius don't count
+        assert_eq!(busyness_score(&[50.0, 80.0], 50.0, 1.0), 0.0);
+
+        let mut distances = Vec::new();
+        let mut last = 0.0;
+        for d in &[40.0, 20.0, 5.0, 1.0, 1.0] {
+            distances.push(*d);
+            let score = busyness_score(&distances, 50.0, 1.0);
+            assert!(score > last && score < 100.0, "{} after {}", score, last);
+            last = score;
+        }
+
+        // a closer plane counts for more, unless the power says every plane counts the same
+        assert!(busyness_score(&[5.0], 50.0, 1.0) > busyness_score(&[40.0], 50.0, 1.0));
+        assert_eq!(
+            busyness_score(&[5.0], 50.0, 0.0),
+            busyness_score(&[40.0], 50.0, 0.0)
+        );
+        // SATURATION planes overhead give about 63
+        let overhead = vec![0.0; BUSYNESS_SATURATION as usize];
+        assert!((busyness_score(&overhead, 50.0, 1.0) - 63.2).abs() < 0.1);
+    }
+}
@@ -0,0 +1,672 @@
+use crate::error::Error;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Point {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+// decimal places shown unless asked otherwise; 4 is about 11 m
+pub(crate) const DEFAULT_COORD_PRECISION: usize = 4;
+
+// "53.2100, -6.1800", with the precision taken from the format string, e.g. {:.2}
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(DEFAULT_COORD_PRECISION);
+        write!(f, "{:.*}, {:.*}", precision, self.lat, precision, self.lon)
+    }
+}
+
+// the coordinate notations accepted on stdin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordFormat {
+    // "12.5, -14.75"
+    Decimal,
+    // "12.5 N\n14.75 W" or "12.5 N 14.75 W"
+    Hemisphere,
+    // "12°30'0\"N 14°45'0\"W"
+    Dms,
+    // "1230.000,N,01445.000,W", bare or inside a GGA/RMC sentence
+    Nmea,
+}
+
+const HEMISPHERES: &[char] = &['N', 'S', 'E', 'W'];
+const DMS_MARKS: &[char] = &['°', 'º', '\'', '"', '′', '″'];
+const DEGREE_MARKS: &[char] = &['°', 'º'];
+
+impl CoordFormat {
+    // an --input-format name; "auto" means detect it, so there's no format to force
+    pub fn from_name(name: &str) -> Result<Option<Self>, String> {
+        match name {
+            "auto" => Ok(None),
+            "decimal" => Ok(Some(CoordFormat::Decimal)),
+            "hemisphere" => Ok(Some(CoordFormat::Hemisphere)),
+            "dms" => Ok(Some(CoordFormat::Dms)),
+            "nmea" => Ok(Some(CoordFormat::Nmea)),
+            _ => Err(format!("Unknown input format: {}", name)),
+        }
+    }
+
+    // guess the notation from its punctuation and hemisphere letters
+    fn detect(s: &str) -> Self {
+        let fields: Vec<&str> = s.split(',').map(str::trim).collect();
+
+        // a degree sign alone, as in "40.7128°, -74.0060°", is only decoration
+        let whole_degrees = !has_hemisphere(s)
+            && s.chars()
+                .all(|c| !DMS_MARKS.contains(&c) || DEGREE_MARKS.contains(&c));
+
+        if nmea_position(&fields).is_some() {
+            CoordFormat::Nmea
+        } else if whole_degrees {
+            CoordFormat::Decimal
+        } else if s.contains(DMS_MARKS) {
+            CoordFormat::Dms
+        } else if has_hemisphere(s) {
+            let multi_part = hemisphere_segments(s)
+                .iter()
+                .any(|(text, _)| text.split_whitespace().count() > 1);
+            if multi_part {
+                CoordFormat::Dms
+            } else {
+                CoordFormat::Hemisphere
+            }
+        } else {
+            CoordFormat::Decimal
+        }
+    }
+}
+
+impl Point {
+    pub fn from_coords(s: &str) -> Result<Self, Error> {
+        Point::from_labeled_coords(s, None).map(|(_, point)| point)
+    }
+
+    // like from_coords, but a first line that can't begin a coordinate, such as "Home",
+    // is taken as a label for the observer, and the notation can be forced rather than
+    // detected
+    pub fn from_labeled_coords(
+        s: &str,
+        format: Option<CoordFormat>,
+    ) -> Result<(Option<String>, Self), Error> {
+        // most likely nothing was piped in at all, so point at both ways of giving a position
+        if s.trim().is_empty() {
+            return Err(Error::Args(
+                "No coordinates provided; pass --lat/--lon or pipe them on stdin".to_string(),
+            ));
+        }
+
+        // tolerate \r\n line endings and stray blank lines, e.g. from a trailing newline
+        let mut lines: Vec<&str> = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let label = match lines.first() {
+            Some(first)
+                if !first.starts_with(|c: char| c.is_ascii_digit() || "+-.$".contains(c)) =>
+            {
+                Some(lines.remove(0).to_string())
+            }
+            _ => None,
+        };
+        let s = lines.join("\n");
+
+        let format = format.unwrap_or_else(|| CoordFormat::detect(&s));
+        let point = Point::parse(&s, format).map_err(|e| {
+            if lines.len() < 2 && count_numbers(&s) < 2 {
+                Error::Coords(format!(
+                    "Expected two coordinate lines, got {}",
+                    lines.len()
+                ))
+            } else {
+                Error::Coords(e)
+            }
+        })?;
+
+        Ok((label, point))
+    }
+
+    fn parse(s: &str, format: CoordFormat) -> Result<Self, String> {
+        let point = match format {
+            CoordFormat::Decimal => Point::parse_decimal(s)?,
+            CoordFormat::Hemisphere => Point::parse_hemisphere(s, 1)?,
+            CoordFormat::Dms => Point::parse_hemisphere(s, 3)?,
+            CoordFormat::Nmea => Point::parse_nmea(s)?,
+        };
+
+        Point::checked(point.lat, point.lon)
+    }
+
+    pub(crate) fn checked(lat: f64, lon: f64) -> Result<Self, String> {
+        if !(-90.0..=90.0).contains(&lat) {
+            return Err(format!("Latitude out of range: {}", lat));
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            return Err(format!("Longitude out of range: {}", lon));
+        }
+
+        Ok(Point { lat, lon })
+    }
+
+    fn parse_decimal(s: &str) -> Result<Self, String> {
+        let values = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|token| token.trim_end_matches(DEGREE_MARKS))
+            .filter(|token| !token.is_empty())
+            .map(parse_coord_value)
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        match values[..] {
+            [lat, lon] => Ok(Point { lat, lon }),
+            _ => Err(format!(
+                "Expected a latitude and a longitude, got {} values",
+                values.len()
+            )),
+        }
+    }
+
+    // hemisphere and DMS notation differ only in how many parts make up each coordinate
+    fn parse_hemisphere(s: &str, max_parts: usize) -> Result<Self, String> {
+        let (last_end, segments) = match hemisphere_letters(s).last() {
+            Some(&(i, _)) => (i + 1, hemisphere_segments(s)),
+            None => (0, Vec::new()),
+        };
+        if !s[last_end..].trim().is_empty() {
+            return Err(format!(
+                "Unexpected text after coordinates: {}",
+                s[last_end..].trim()
+            ));
+        }
+
+        let mut lat = None;
+        let mut lon = None;
+        for (text, hemisphere) in &segments {
+            // commas may separate the values from their letters, as in "53.21,N,6.18,W"
+            let parts = text
+                .split(|c: char| c.is_whitespace() || c == ',' || DMS_MARKS.contains(&c))
+                .filter(|token| !token.is_empty())
+                .map(parse_coord_value)
+                .collect::<Result<Vec<f64>, String>>()?;
+            if parts.is_empty() || parts.len() > max_parts {
+                return Err(format!(
+                    "Expected up to {} number(s) before {}, got {}",
+                    max_parts,
+                    hemisphere,
+                    parts.len()
+                ));
+            }
+
+            let value = from_dms(&parts)?;
+            let slot = match hemisphere {
+                'N' | 'S' => &mut lat,
+                _ => &mut lon,
+            };
+            if slot.is_some() {
+                return Err(format!(
+                    "Duplicate coordinate for hemisphere {}",
+                    hemisphere
+                ));
+            }
+            *slot = Some(match hemisphere {
+                'S' | 'W' => -value,
+                _ => value,
+            });
+        }
+
+        match (lat, lon) {
+            (Some(lat), Some(lon)) => Ok(Point { lat, lon }),
+            _ => Err("Expected one N/S latitude and one E/W longitude".to_string()),
+        }
+    }
+
+    fn parse_nmea(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split(',').map(str::trim).collect();
+        let i = nmea_position(&fields).ok_or("Expected NMEA fields like 4807.038,N,01131.000,E")?;
+
+        let lat = from_nmea(fields[i])?;
+        let lon = from_nmea(fields[i + 2])?;
+
+        Ok(Point {
+            lat: if fields[i + 1] == "S" { -lat } else { lat },
+            lon: if fields[i + 3] == "W" { -lon } else { lon },
+        })
+    }
+}
+
+pub(crate) fn parse_coord_value(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v.is_finite() => Ok(v),
+        _ => Err(format!("Float parse failure on {}", s)),
+    }
+}
+
+// how many numbers appear anywhere in the text, to tell a missing line from a malformed one
+fn count_numbers(s: &str) -> usize {
+    s.split(|c: char| {
+        c == ',' || c.is_whitespace() || DMS_MARKS.contains(&c) || HEMISPHERES.contains(&c)
+    })
+    .filter(|token| token.parse::<f64>().is_ok())
+    .count()
+}
+
+// whether any hemisphere letter appears
+fn has_hemisphere(s: &str) -> bool {
+    !hemisphere_letters(s).is_empty()
+}
+
+// each hemisphere letter and where it is, not counting the E of an exponent like 5.321E1
+fn hemisphere_letters(s: &str) -> Vec<(usize, char)> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &(_, c))| {
+            let exponent = c == 'E'
+                && i > 0
+                && (chars[i - 1].1.is_ascii_digit() || chars[i - 1].1 == '.')
+                && chars
+                    .get(i + 1)
+                    .is_some_and(|&(_, next)| next.is_ascii_digit() || "+-".contains(next));
+            HEMISPHERES.contains(&c) && !exponent
+        })
+        .map(|(_, &letter)| letter)
+        .collect()
+}
+
+// split text into the chunks preceding each hemisphere letter, paired with that letter
+fn hemisphere_segments(s: &str) -> Vec<(&str, char)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in hemisphere_letters(s) {
+        segments.push((&s[start..i], c));
+        start = i + c.len_utf8();
+    }
+
+    segments
+}
+
+fn from_dms(parts: &[f64]) -> Result<f64, String> {
+    if parts[1..].iter().any(|&v| !(0.0..60.0).contains(&v)) {
+        return Err(format!("Minutes and seconds must be below 60: {:?}", parts));
+    }
+
+    Ok(parts
+        .iter()
+        .zip(&[1.0, 60.0, 3600.0])
+        .map(|(v, scale)| v / scale)
+        .sum())
+}
+
+// index of the latitude in a run of "ddmm.mm,N,dddmm.mm,E" fields
+fn nmea_position(fields: &[&str]) -> Option<usize> {
+    (0..fields.len().saturating_sub(3))
+        .find(|&i| matches!(fields[i + 1], "N" | "S") && matches!(fields[i + 3], "E" | "W"))
+}
+
+// NMEA packs degrees and minutes together, e.g. 4807.038 is 48°07.038'
+fn from_nmea(s: &str) -> Result<f64, String> {
+    let v = parse_coord_value(s)?;
+    let degrees = (v / 100.0).trunc();
+    let minutes = v - degrees * 100.0;
+    from_dms(&[degrees, minutes])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_point() {
+        let p: Point = Point {
+            lat: 12.5,
+            lon: -14.75,
+        };
+
+        let coords = "12.5 N\n14.75 W";
+
+        assert_eq!(p, Point::from_coords(coords).unwrap());
+    }
+
+    // (input, expected (lat, lon)) pairs; None marks input that must be rejected
+    const COORD_FIXTURES: &[(&str, Option<(f64, f64)>)] = &[
+        // decimal, signed
+        ("12.5, -14.75", Some((12.5, -14.75))),
+        ("-33.8688 151.2093", Some((-33.8688, 151.2093))),
+        ("53.21\n-6.18\n", Some((53.21, -6.18))),
+        ("53.21\r\n-6.18\r\n\r\n", Some((53.21, -6.18))),
+        ("5.321E1, -6.18", Some((53.21, -6.18))),
+        ("5.321E+1 -618E-2", Some((53.21, -6.18))),
+        // hemisphere, two lines
+        ("12.5 N\n14.75 W", Some((12.5, -14.75))),
+        ("33.8688 S\n151.2093 E\n", Some((-33.8688, 151.2093))),
+        ("14.75 W\n12.5 N", Some((12.5, -14.75))),
+        ("\n12.5 N\r\n\r\n14.75 W\r\n", Some((12.5, -14.75))),
+        // hemisphere, single line
+        ("53.21 N 6.18 W", Some((53.21, -6.18))),
+        ("5.321E1 N\n6.18 W", Some((53.21, -6.18))),
+        ("5.321E1N 6.18E0E", Some((53.21, 6.18))),
+        ("40.7128N 74.0060W", Some((40.7128, -74.006))),
+        // degrees, minutes, seconds
+        ("40°42'46\"N 74°0'21.6\"W", Some((40.712778, -74.006))),
+        ("33 52 7.7 S\n151 12 33.5 E", Some((-33.868806, 151.209306))),
+        ("12°30′N 14°45′W", Some((12.5, -14.75))),
+        // decorated, as pasted from websites
+        ("40.7128° N, 74.0060° W", Some((40.7128, -74.006))),
+        ("40.7128°N,74.0060°W", Some((40.7128, -74.006))),
+        ("40.7128° N,\n74.0060° W\n", Some((40.7128, -74.006))),
+        ("40° 42′ 46″ N, 74° 0′ 21.6″ W", Some((40.712778, -74.006))),
+        ("40.7128°, -74.0060°", Some((40.7128, -74.006))),
+        ("-33.8688º 151.2093º", Some((-33.8688, 151.2093))),
+        // NMEA fields, bare and inside a GGA sentence
+        ("4807.038,N,01131.000,E", Some((48.1173, 11.516667))),
+        (
+            "$GPGGA,123519,4807.038,N,01131.000,W,1,08,0.9,545.4,M,46.9,M,,*47",
+            Some((48.1173, -11.516667)),
+        ),
+        (
+            "$GPGGA,123519,4807.038,N,01131.000,W,1,08,0.9,545.4,M,46.9,M,,*47\r\n",
+            Some((48.1173, -11.516667)),
+        ),
+        ("3352.128,S,15112.558,E", Some((-33.8688, 151.209300))),
+        // invalid
+        ("12.5 N\nfoo W", None),
+        ("12.5", None),
+        ("", None),
+        ("91.0 N\n10.0 E", None),
+        ("12.5 N\n14.75 N", None),
+        ("40°75'0\"N 74°0'0\"W", None),
+        ("12.5 N 14.75 W extra", None),
+    ];
+
+    #[test]
+    fn test_degree_symbols_and_comma() {
+        let p = Point::from_coords("40.7128° N, 74.0060° W").unwrap();
+        assert_eq!(
+            p,
+            Point {
+                lat: 40.7128,
+                lon: -74.006
+            }
+        );
+        assert_eq!(Point::from_coords("40.7128°, -74.0060°").unwrap(), p);
+    }
+
+    #[test]
+    fn test_labeled_coords() {
+        let (label, p) = Point::from_labeled_coords("Home\n40.7 N\n74.0 W\n", None).unwrap();
+        assert_eq!(label.as_deref(), Some("Home"));
+        assert_eq!(
+            p,
+            Point {
+                lat: 40.7,
+                lon: -74.0
+            }
+        );
+
+        let (label, p) =
+            Point::from_labeled_coords("Site 2 (roof)\r\n-33.87, 151.21", None).unwrap();
+        assert_eq!(label.as_deref(), Some("Site 2 (roof)"));
+        assert_eq!(
+            p,
+            Point {
+                lat: -33.87,
+                lon: 151.21
+            }
+        );
+        // plain from_coords just drops the label
+        assert_eq!(
+            Point::from_coords("Home\n40.7 N\n74.0 W").unwrap(),
+            Point {
+                lat: 40.7,
+                lon: -74.0
+            }
+        );
+
+        let (label, _) = Point::from_labeled_coords("40.7 N\n74.0 W", None).unwrap();
+        assert_eq!(label, None);
+        let (label, _) = Point::from_labeled_coords(
+            "$GPGGA,123519,4807.038,N,01131.000,W,1,08,0.9,545.4,M,46.9,M,,*47",
+            None,
+        )
+        .unwrap();
+        assert_eq!(label, None);
+
+        assert!(Point::from_labeled_coords("Home", None).is_err());
+    }
+
+    #[test]
+    fn test_display_point() {
+        let p = Point {
+            lat: 53.421337,
+            lon: -6.270075,
+        };
+        assert_eq!(p.to_string(), "53.4213, -6.2701");
+        assert_eq!(format!("{:.2}", p), "53.42, -6.27");
+        assert_eq!(format!("{:.5}", p), "53.42134, -6.27008");
+        assert_eq!(format!("{:.0}", p), "53, -6");
+    }
+
+    #[test]
+    fn test_forced_input_format() {
+        let parse = |s, format| Point::from_labeled_coords(s, format).map(|(_, p)| p);
+
+        // detected as NMEA degrees and minutes, or read as plain degrees when forced
+        let nmea = "0053.210,N,00006.180,W";
+        let p = parse(nmea, None).unwrap();
+        assert!((p.lat - 0.8868).abs() < 1e-4 && (p.lon + 0.103).abs() < 1e-4);
+        assert_eq!(parse(nmea, Some(CoordFormat::Nmea)).unwrap(), p);
+        assert_eq!(
+            parse(nmea, Some(CoordFormat::Hemisphere)).unwrap(),
+            Point {
+                lat: 53.21,
+                lon: -6.18
+            }
+        );
+
+        // detected as degrees and minutes, which hemisphere notation doesn't allow
+        let dms = "12 30 N\n14 45 W";
+        assert_eq!(
+            parse(dms, None).unwrap(),
+            Point {
+                lat: 12.5,
+                lon: -14.75
+            }
+        );
+        let err = parse(dms, Some(CoordFormat::Hemisphere)).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Expected up to 1 number(s) before N"));
+
+        assert!(parse("12.5 N 14.75 W", Some(CoordFormat::Decimal)).is_err());
+        assert!(parse("53.21, -6.18", Some(CoordFormat::Nmea)).is_err());
+
+        assert_eq!(CoordFormat::from_name("auto"), Ok(None));
+        assert_eq!(CoordFormat::from_name("dms"), Ok(Some(CoordFormat::Dms)));
+        assert!(CoordFormat::from_name("utm").is_err());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        for input in ["", "\n", " \r\n\t\n"] {
+            let err = Point::from_coords(input).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "No coordinates provided; pass --lat/--lon or pipe them on stdin"
+            );
+            assert_eq!(err.exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn test_missing_coordinate_line() {
+        for input in ["53.21 N", "53.21 N\n\n", "53.21\r\n"] {
+            assert!(matches!(
+                Point::from_coords(input),
+                Err(Error::Coords(e)) if e == "Expected two coordinate lines, got 1"
+            ));
+        }
+        assert!(matches!(
+            Point::from_coords("Home\r\n"),
+            Err(Error::Coords(e)) if e == "Expected two coordinate lines, got 0"
+        ));
+
+        // a malformed second line is reported as such rather than as missing
+        let err = Point::from_coords("53.21 N\nsix W")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Float parse failure"), "{}", err);
+    }
+
+    // awkward inputs worth checking every time; anything that ever trips the parser
+    // belongs here too
+    const REGRESSION_CORPUS: &[&str] = &[
+        "N",
+        "NSEW",
+        "°′″",
+        ",N,,E",
+        "$GPGGA,,N,,E",
+        "9999.999,N,99999.999,E",
+        "1e400 1e400",
+        "-0 -0",
+        "90.0000001 0",
+        "😀\n53.21\n-6.18",
+        "53° 60′ N 6° 0′ W",
+        "Home\nAway\n53.21\n-6.18",
+    ];
+
+    // the parser never panics, and anything it accepts is a real position
+    fn assert_parses_cleanly(input: &str) {
+        let formats = [
+            None,
+            Some(CoordFormat::Decimal),
+            Some(CoordFormat::Hemisphere),
+            Some(CoordFormat::Dms),
+            Some(CoordFormat::Nmea),
+        ];
+        for &format in &formats {
+            let parsed = std::panic::catch_unwind(|| Point::from_labeled_coords(input, format))
+                .unwrap_or_else(|_| panic!("{:?} panicked the {:?} parser", input, format));
+            if let Ok((_, p)) = parsed {
+                assert!(
+                    (-90.0..=90.0).contains(&p.lat) && (-180.0..=180.0).contains(&p.lon),
+                    "{:?} parsed to out-of-range {:?}",
+                    input,
+                    p
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_arbitrary_input() {
+        // a fixed xorshift sequence, so any failure reproduces
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        let pieces = [
+            "0",
+            "1",
+            "5",
+            "9",
+            "12",
+            "53.21",
+            "-6.18",
+            "180",
+            "-90.0001",
+            "4807.038",
+            "01131.000",
+            "1e400",
+            "nan",
+            "inf",
+            "-",
+            "+",
+            ".",
+            ",",
+            " ",
+            "\n",
+            "\r\n",
+            "\t",
+            "N",
+            "S",
+            "E",
+            "W",
+            "°",
+            "º",
+            "'",
+            "\"",
+            "′",
+            "″",
+            "$GPGGA",
+            "Home",
+            "é",
+            "😀",
+            "",
+        ];
+
+        for _ in 0..5_000 {
+            let len = next(12);
+            let input: String = (0..len).map(|_| pieces[next(pieces.len())]).collect();
+            assert_parses_cleanly(&input);
+        }
+
+        // near misses of inputs that do parse: a character dropped, doubled or swapped in
+        for (input, _) in COORD_FIXTURES {
+            let chars: Vec<char> = input.chars().collect();
+            for _ in 0..50 {
+                let mut mutated = chars.clone();
+                let i = next(mutated.len().max(1));
+                match next(3) {
+                    0 if !mutated.is_empty() => {
+                        mutated.remove(i);
+                    }
+                    1 if !mutated.is_empty() => mutated.insert(i, mutated[i]),
+                    _ => {
+                        let piece = pieces[next(pieces.len())];
+                        mutated.splice(i.min(mutated.len())..i.min(mutated.len()), piece.chars());
+                    }
+                }
+                assert_parses_cleanly(&mutated.into_iter().collect::<String>());
+            }
+        }
+
+        for input in REGRESSION_CORPUS {
+            assert_parses_cleanly(input);
+        }
+    }
+
+    #[test]
+    fn test_parse_coord_fixtures() {
+        const EPSILON: f64 = 1e-5;
+
+        for (input, expected) in COORD_FIXTURES {
+            let parsed = Point::from_coords(input);
+            match expected {
+                Some((lat, lon)) => {
+                    let p = parsed.unwrap_or_else(|e| panic!("{:?} failed to parse: {}", input, e));
+                    assert!(
+                        (p.lat - lat).abs() < EPSILON && (p.lon - lon).abs() < EPSILON,
+                        "{:?} parsed to {:?}, expected ({}, {})",
+                        input,
+                        p,
+                        lat,
+                        lon
+                    );
+                }
+                None => assert!(
+                    parsed.is_err(),
+                    "{:?} should not parse: {:?}",
+                    input,
+                    parsed
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::env;
+use std::fmt;
+use std::fs;
+
+// an OpenSky account, sent along with each request as basic auth
+#[derive(Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    // either a single "user:pass" line, or username = "..." and password = "..." lines
+    // as in a small TOML file; blank lines and lines starting with # are skipped
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        let (username, password) = match lines[..] {
+            [line] if !is_key_value(line) => line
+                .split_once(':')
+                .map(|(user, pass)| (Some(user.to_string()), Some(pass.to_string())))
+                .ok_or("expected user:pass")?,
+            _ => {
+                let (mut username, mut password) = (None, None);
+                for line in lines {
+                    let (key, value) = line
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected key = value, got: {}", line))?;
+                    let value = value.trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|v| v.strip_suffix('"'))
+                        .unwrap_or(value)
+                        .to_string();
+                    match key.trim() {
+                        "username" => username = Some(value),
+                        "password" => password = Some(value),
+                        other => return Err(format!("unknown key: {}", other)),
+                    }
+                }
+                (username, password)
+            }
+        };
+
+        match (username, password) {
+            (Some(username), Some(password)) if !username.is_empty() => {
+                Ok(Credentials { username, password })
+            }
+            _ => Err("expected a username and a password".to_string()),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        if is_world_readable(path) {
+            eprintln!(
+                "Warning: {} is readable by other users; consider chmod 600",
+                path
+            );
+        }
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Credentials::parse(&text)
+    }
+
+    // the basic auth header value; attohttpc only offers basic_auth on some TLS builds
+    pub(crate) fn authorization(&self) -> String {
+        format!(
+            "Basic {}",
+            base64(format!("{}:{}", self.username, self.password).as_bytes())
+        )
+    }
+
+    // OPENSKY_USERNAME and OPENSKY_PASSWORD, when both are set
+    pub fn from_env() -> Option<Self> {
+        match (env::var("OPENSKY_USERNAME"), env::var("OPENSKY_PASSWORD")) {
+            (Ok(username), Ok(password)) => Some(Credentials { username, password }),
+            _ => None,
+        }
+    }
+}
+
+// keep the password out of debug output and logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+fn is_key_value(line: &str) -> bool {
+    line.split_once('=')
+        .is_some_and(|(key, _)| matches!(key.trim(), "username" | "password"))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(unix)]
+fn is_world_readable(path: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o004 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_world_readable(_path: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_credentials() {
+        let expected = Credentials {
+            username: "skywatcher".to_string(),
+            password: "pa:ss=word".to_string(),
+        };
+        assert_eq!(
+            Credentials::parse("skywatcher:pa:ss=word\n").unwrap(),
+            expected
+        );
+
+        let toml = "# OpenSky account for the cron job\n\
+                    username = \"skywatcher\"\n\
+                    password = \"pa:ss=word\"\n";
+        assert_eq!(Credentials::parse(toml).unwrap(), expected);
+
+        assert!(Credentials::parse("").is_err());
+        assert!(Credentials::parse("skywatcher").is_err());
+        assert!(Credentials::parse("username = \"skywatcher\"").is_err());
+        assert!(Credentials::parse("user = \"skywatcher\"\npassword = \"x\"").is_err());
+        assert!(!format!("{:?}", expected).contains("pa:ss"));
+    }
+
+    #[test]
+    fn test_authorization_header() {
+        let credentials = Credentials {
+            username: "Aladdin".to_string(),
+            password: "open sesame".to_string(),
+        };
+        assert_eq!(
+            credentials.authorization(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_world_readable_check() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("nearest_airplane_creds_{}", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, "skywatcher:secret\n").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(is_world_readable(path_str));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(!is_world_readable(path_str));
+        assert_eq!(
+            Credentials::from_file(path_str).unwrap().username,
+            "skywatcher"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Everything that can stop the tool from reporting a plane.
+#[derive(Debug)]
+pub enum Error {
+    /// Unusable command line arguments.
+    Args(String),
+    /// A configuration or reference file that couldn't be read or understood.
+    Config(String),
+    /// Observer coordinates that couldn't be parsed.
+    Coords(String),
+    /// The request to OpenSky failed.
+    Network(String),
+    /// OpenSky answered with something other than the expected JSON.
+    Json(serde_json::Error),
+    /// No plane survived the filters.
+    NoResults,
+    /// `--any` found no plane within `--radius`; its `false` is the whole answer, so this
+    /// is never printed.
+    NoneWithinRadius,
+    /// The `--track` plane isn't among the plane states, or was filtered out.
+    NotVisible(String),
+    /// `--watch-timeout` ran out before a plane came within `--watch-until-radius`.
+    WatchTimeout(u64),
+    /// The result couldn't be written out.
+    Output(io::Error),
+}
+
+impl Error {
+    /// The process exit code for this error, one for each kind so scripts can tell them
+    /// apart; 1 is left for `--any`'s `false`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoneWithinRadius => 1,
+            Error::Args(_) => 2,
+            Error::NoResults => 3,
+            Error::Network(_) => 4,
+            Error::Json(_) => 5,
+            Error::Config(_) => 6,
+            Error::Coords(_) => 7,
+            Error::NotVisible(_) => 8,
+            Error::WatchTimeout(_) => 9,
+            Error::Output(_) => 10,
+        }
+    }
+
+    /// A short, stable name for the kind of error, for scripts that want to tell them apart.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Args(_) => "args",
+            Error::Config(_) => "config",
+            Error::Coords(_) => "coords",
+            Error::Network(_) => "network",
+            Error::Json(_) => "json",
+            Error::NoResults => "no_results",
+            Error::NoneWithinRadius => "none_within_radius",
+            Error::NotVisible(_) => "not_visible",
+            Error::WatchTimeout(_) => "watch_timeout",
+            Error::Output(_) => "output",
+        }
+    }
+
+    /// The error as a one-line JSON object, for `--json` consumers.
+    pub fn to_json(&self) -> String {
+        serde_json::json!({ "error": self.to_string(), "kind": self.kind() }).to_string()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Args(msg) | Error::Config(msg) => write!(f, "{}", msg),
+            Error::Coords(msg) => write!(f, "Couldn't parse input coordinates: {}", msg),
+            Error::Network(msg) => write!(f, "Error calling Opensky API: {}", msg),
+            Error::Json(e) => write!(f, "Couldn't parse the Opensky response: {}", e),
+            Error::NoResults => write!(f, "No planes matched; try relaxing the filters."),
+            Error::NoneWithinRadius => write!(f, "No plane within --radius"),
+            Error::NotVisible(icao24) => write!(f, "{} isn't visible from either observer", icao24),
+            Error::WatchTimeout(secs) => {
+                write!(f, "No plane came close enough within {} seconds", secs)
+            }
+            Error::Output(e) => write!(f, "Couldn't write result: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Json(e) => Some(e),
+            Error::Output(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error_messages() {
+        let json_error = serde_json::from_str::<serde_json::Value>("{\"states\":").unwrap_err();
+        let cases = [
+            (
+                Error::Args("Unknown argument: --speed".to_string()),
+                "Unknown argument: --speed",
+            ),
+            (
+                Error::Config("Couldn't read places from airports.csv: not found".to_string()),
+                "Couldn't read places from airports.csv: not found",
+            ),
+            (
+                Error::Coords("Expected two coordinate lines, got 1".to_string()),
+                "Couldn't parse input coordinates: Expected two coordinate lines, got 1",
+            ),
+            (
+                Error::Network("connection refused".to_string()),
+                "Error calling Opensky API: connection refused",
+            ),
+            (
+                Error::Json(json_error),
+                "Couldn't parse the Opensky response: EOF while parsing a value at line 1 column 10",
+            ),
+            (
+                Error::NoResults,
+                "No planes matched; try relaxing the filters.",
+            ),
+            (Error::NoneWithinRadius, "No plane within --radius"),
+            (
+                Error::NotVisible("4ca123".to_string()),
+                "4ca123 isn't visible from either observer",
+            ),
+            (
+                Error::WatchTimeout(600),
+                "No plane came close enough within 600 seconds",
+            ),
+            (
+                Error::Output(io::Error::new(io::ErrorKind::PermissionDenied, "read-only")),
+                "Couldn't write result: read-only",
+            ),
+        ];
+
+        for (error, message) in &cases {
+            assert_eq!(error.to_string(), *message);
+        }
+        let codes: Vec<i32> = cases.iter().map(|(error, _)| error.exit_code()).collect();
+        assert_eq!(codes, [2, 6, 7, 4, 5, 3, 1, 8, 9, 10]);
+        assert!(cases[4].0.source().is_some());
+        assert!(cases[5].0.source().is_none());
+    }
+
+    #[test]
+    fn test_error_json() {
+        let error = Error::Network("connection refused".to_string());
+        let json: serde_json::Value = serde_json::from_str(&error.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": "Error calling Opensky API: connection refused",
+                "kind": "network",
+            })
+        );
+        assert_eq!(Error::NoResults.kind(), "no_results");
+    }
+}
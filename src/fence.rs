@@ -0,0 +1,97 @@
+use crate::coords::Point;
+
+// a polygon on the map, treated as flat: edges are straight lines in lat/lon, which is
+// close enough for sector-sized areas away from the poles and the antimeridian
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fence {
+    vertices: Vec<Point>,
+}
+
+impl Fence {
+    // one "lon,lat" vertex per line, in order around the edge; the polygon closes itself,
+    // and blank lines and lines starting with # are skipped
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let vertices = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_no, line)| {
+                let values = line
+                    .split(',')
+                    .map(|value| value.trim().parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| format!("line {}: invalid vertex: {}", line_no, line))?;
+                match values[..] {
+                    [lon, lat] => {
+                        Point::checked(lat, lon).map_err(|e| format!("line {}: {}", line_no, e))
+                    }
+                    _ => Err(format!("line {}: expected lon,lat", line_no)),
+                }
+            })
+            .collect::<Result<Vec<Point>, String>>()?;
+
+        if vertices.len() < 3 {
+            return Err(format!(
+                "A fence needs at least 3 vertices, got {}",
+                vertices.len()
+            ));
+        }
+
+        Ok(Fence { vertices })
+    }
+
+    // ray casting: count how many edges a ray heading east from the point crosses
+    pub fn contains(&self, p: &Point) -> bool {
+        let mut inside = false;
+        let mut prev = self.vertices[self.vertices.len() - 1];
+
+        for &vertex in &self.vertices {
+            if (vertex.lat > p.lat) != (prev.lat > p.lat) {
+                let crossing_lon = vertex.lon
+                    + (p.lat - vertex.lat) / (prev.lat - vertex.lat) * (prev.lon - vertex.lon);
+                if p.lon < crossing_lon {
+                    inside = !inside;
+                }
+            }
+            prev = vertex;
+        }
+
+        inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_fence() {
+        let fence = Fence::parse("# Dublin-ish\n-7,53\n-6,53\n-6,54\n-7,54\n").unwrap();
+        let inside = |lat, lon| fence.contains(&Point { lat, lon });
+
+        assert!(inside(53.5, -6.5));
+        assert!(inside(53.01, -6.99));
+        assert!(!inside(53.5, -5.5));
+        assert!(!inside(52.9, -6.5));
+        assert!(!inside(54.5, -7.5));
+    }
+
+    #[test]
+    fn test_concave_fence() {
+        // an L shape: the top right square is cut out
+        let fence = Fence::parse("0,0\n2,0\n2,1\n1,1\n1,2\n0,2").unwrap();
+        assert!(fence.contains(&Point { lat: 1.5, lon: 0.5 }));
+        assert!(fence.contains(&Point { lat: 0.5, lon: 1.5 }));
+        assert!(!fence.contains(&Point { lat: 1.5, lon: 1.5 }));
+    }
+
+    #[test]
+    fn test_parse_fence() {
+        assert!(Fence::parse("-7,53\n-6,53").is_err());
+        assert!(Fence::parse("-7,53\n-6,53\nnorth,54").is_err());
+        assert!(Fence::parse("-7,53\n-6,53\n-6,54,100").is_err());
+        // latitude comes second
+        assert!(Fence::parse("0,0\n0,95\n1,0").is_err());
+    }
+}
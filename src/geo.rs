@@ -0,0 +1,364 @@
+use crate::coords::Point;
+
+/// Mean Earth radius in km, used unless `--earth-radius` says otherwise.
+pub const EARTH_RADIUS_KM: f64 = 6372.8;
+
+/// Great-circle distance in km between two points, on a sphere of radius [`EARTH_RADIUS_KM`].
+///
+/// ```
+/// use nearest_airplane::{haversine, Point};
+///
+/// let nashville = Point { lat: 36.12, lon: -86.67 };
+/// let los_angeles = Point { lat: 33.94, lon: -118.4 };
+/// assert!((haversine(&nashville, &los_angeles) - 2887.26).abs() < 0.01);
+/// ```
+pub fn haversine(origin: &Point, destination: &Point) -> f64 {
+    haversine_with_radius(origin, destination, EARTH_RADIUS_KM)
+}
+
+/// Like [`haversine`], but on a sphere of the given radius; the result is in the radius's units.
+///
+/// ```
+/// use nearest_airplane::{haversine_with_radius, Point};
+///
+/// let nashville = Point { lat: 36.12, lon: -86.67 };
+/// let los_angeles = Point { lat: 33.94, lon: -118.4 };
+/// // WGS-84 equatorial radius
+/// let km = haversine_with_radius(&nashville, &los_angeles, 6378.137);
+/// assert!((km - 2889.68).abs() < 0.01);
+/// ```
+pub fn haversine_with_radius(origin: &Point, destination: &Point, radius: f64) -> f64 {
+    // Haversine formula implementation adapted from
+    // https://rosettacode.org/wiki/Haversine_formula#Rust
+    let o_lon = (origin.lon - destination.lon).to_radians();
+    let o_lat = origin.lat.to_radians();
+    let d_lat = destination.lat.to_radians();
+
+    let dz: f64 = o_lat.sin() - d_lat.sin();
+    let dx: f64 = o_lon.cos() * o_lat.cos() - d_lat.cos();
+    let dy: f64 = o_lon.sin() * o_lat.cos();
+
+    // rounding can push the half-chord of antipodal points just past 1, outside asin's domain
+    ((dx * dx + dy * dy + dz * dz).sqrt() / 2.0).min(1.0).asin() * 2.0 * radius
+}
+
+/// Initial great-circle bearing from one point to another, in degrees clockwise from north
+/// in the range `0.0..360.0`.
+///
+/// ```
+/// use nearest_airplane::{bearing, Point};
+///
+/// let nashville = Point { lat: 36.12, lon: -86.67 };
+/// let los_angeles = Point { lat: 33.94, lon: -118.4 };
+/// // heading west, bending a little north of west at first
+/// assert!((bearing(&nashville, &los_angeles) - 274.59).abs() < 0.01);
+///
+/// let equator = Point { lat: 0.0, lon: 0.0 };
+/// assert_eq!(bearing(&equator, &Point { lat: 0.0, lon: 1.0 }), 90.0);
+/// ```
+pub fn bearing(from: &Point, to: &Point) -> f64 {
+    let (from_lat, to_lat) = (from.lat.to_radians(), to.lat.to_radians());
+    let d_lon = (to.lon - from.lon).to_radians();
+
+    let y = d_lon.sin() * to_lat.cos();
+    let x = from_lat.cos() * to_lat.sin() - from_lat.sin() * to_lat.cos() * d_lon.cos();
+
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+// a cheap first pass for radius searches: anything further than max_dlat or max_dlon
+// degrees from the center can't be within the radius, so it needn't go through haversine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Prefilter {
+    center: Point,
+    max_dlat: f64,
+    // None when the circle reaches over a pole, so every longitude is possible
+    max_dlon: Option<f64>,
+}
+
+impl Prefilter {
+    // the bounding box of a circle on the sphere, after
+    // http://janmatuschek.de/LatitudeLongitudeBoundingCoordinates
+    pub(crate) fn new(center: Point, radius_km: f64, sphere_radius: f64) -> Self {
+        // a sliver of slack so that rounding can't turn a boundary plane away
+        let angular = radius_km / sphere_radius * (1.0 + 1e-9);
+        let ratio = angular.sin() / center.lat.to_radians().cos();
+        let max_dlon = if angular < std::f64::consts::FRAC_PI_2 && ratio < 1.0 {
+            Some(ratio.asin().to_degrees())
+        } else {
+            None
+        };
+
+        Prefilter {
+            center,
+            max_dlat: angular.to_degrees(),
+            max_dlon,
+        }
+    }
+
+    pub(crate) fn may_contain(&self, p: &Point) -> bool {
+        if (p.lat - self.center.lat).abs() > self.max_dlat {
+            return false;
+        }
+
+        self.max_dlon.is_none_or(|max_dlon| {
+            // the short way round, across the antimeridian if need be
+            let dlon = (p.lon - self.center.lon).abs() % 360.0;
+            dlon.min(360.0 - dlon) <= max_dlon
+        })
+    }
+}
+
+// angle above the horizon of something at the given altitude and ground distance; the
+// Earth's curvature is ignored, which only matters near the horizon
+pub(crate) fn elevation_angle(ground_km: f64, altitude_m: f64) -> f64 {
+    (altitude_m / 1000.0).atan2(ground_km).to_degrees()
+}
+
+// distance in km from the observer to the great circle the plane is flying along,
+// positive when the observer is to the right of the plane's course
+pub(crate) fn cross_track_distance(
+    observer: &Point,
+    plane: &Point,
+    track: f64,
+    radius: f64,
+) -> f64 {
+    let angular = haversine_with_radius(plane, observer, radius) / radius;
+    let relative = (bearing(plane, observer) - track).to_radians();
+
+    (angular.sin() * relative.sin()).asin() * radius
+}
+
+// distance in km along the plane's course to the point closest to the observer, negative
+// when that point is already behind it
+pub(crate) fn along_track_distance(
+    observer: &Point,
+    plane: &Point,
+    track: f64,
+    radius: f64,
+) -> f64 {
+    let angular = haversine_with_radius(plane, observer, radius) / radius;
+    let relative = (bearing(plane, observer) - track).to_radians();
+    let cross_track = (angular.sin() * relative.sin()).asin();
+    let along = (angular.cos() / cross_track.cos()).clamp(-1.0, 1.0).acos();
+
+    along * relative.cos().signum() * radius
+}
+
+// n evenly spaced points along the great circle from a to b, including both ends.
+// Antipodal endpoints have no unique great circle, so their waypoints are undefined (NaN).
+pub(crate) fn great_circle_waypoints(a: &Point, b: &Point, n: usize) -> Vec<Point> {
+    let (a_lat, a_lon) = (a.lat.to_radians(), a.lon.to_radians());
+    let (b_lat, b_lon) = (b.lat.to_radians(), b.lon.to_radians());
+
+    // angular distance between the endpoints
+    let d = ((a_lat.sin() * b_lat.sin() + a_lat.cos() * b_lat.cos() * (b_lon - a_lon).cos())
+        .clamp(-1.0, 1.0))
+    .acos();
+
+    (0..n)
+        .map(|i| {
+            if d < 1e-12 {
+                return Point {
+                    lat: a.lat,
+                    lon: a.lon,
+                };
+            }
+
+            let f = if n > 1 {
+                i as f64 / (n - 1) as f64
+            } else {
+                0.0
+            };
+            let wa = ((1.0 - f) * d).sin() / d.sin();
+            let wb = (f * d).sin() / d.sin();
+
+            let x = wa * a_lat.cos() * a_lon.cos() + wb * b_lat.cos() * b_lon.cos();
+            let y = wa * a_lat.cos() * a_lon.sin() + wb * b_lat.cos() * b_lon.sin();
+            let z = wa * a_lat.sin() + wb * b_lat.sin();
+
+            Point {
+                lat: z.atan2((x * x + y * y).sqrt()).to_degrees(),
+                lon: y.atan2(x).to_degrees(),
+            }
+        })
+        .collect()
+}
+
+// the course at the halfway point of the great circle from a to b, which drifts away from
+// the initial bearing as the path gets longer
+pub(crate) fn midpoint_bearing(a: &Point, b: &Point) -> f64 {
+    let midpoint = great_circle_waypoints(a, b, 3)[1];
+    bearing(&midpoint, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haversine() {
+        let origin: Point = Point {
+            lat: 36.12,
+            lon: -86.67,
+        };
+        let destination: Point = Point {
+            lat: 33.94,
+            lon: -118.4,
+        };
+
+        assert!((haversine(&origin, &destination) - 2887.2599506071106).powi(2) < 0.00001);
+    }
+
+    #[test]
+    fn test_haversine_identical_points() {
+        for &(lat, lon) in &[(0.0, 0.0), (53.21, -6.18), (-33.87, 151.21), (90.0, 0.0)] {
+            let p = Point { lat, lon };
+            assert!(haversine(&p, &p).abs() < 1e-9, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_haversine_antipodal_points() {
+        // half the circumference of a sphere with the formula's R = 6372.8 km
+        let half_circumference = std::f64::consts::PI * 6372.8;
+        let pairs = [
+            ((0.0, 0.0), (0.0, 180.0)),
+            ((45.0, 10.0), (-45.0, -170.0)),
+            ((53.21, -6.18), (-53.21, 173.82)),
+            ((90.0, 0.0), (-90.0, 0.0)),
+        ];
+
+        for &((a_lat, a_lon), (b_lat, b_lon)) in &pairs {
+            let a = Point {
+                lat: a_lat,
+                lon: a_lon,
+            };
+            let b = Point {
+                lat: b_lat,
+                lon: b_lon,
+            };
+            let d = haversine(&a, &b);
+            assert!(
+                (d - half_circumference).abs() < 1e-3,
+                "{:?} to {:?} gave {}",
+                a,
+                b,
+                d
+            );
+        }
+    }
+
+    #[test]
+    fn test_great_circle_waypoints() {
+        let origin = Point {
+            lat: 36.12,
+            lon: -86.67,
+        };
+        let destination = Point {
+            lat: 33.94,
+            lon: -118.4,
+        };
+
+        let waypoints = great_circle_waypoints(&origin, &destination, 5);
+        assert_eq!(waypoints.len(), 5);
+
+        let (first, last) = (&waypoints[0], &waypoints[4]);
+        assert!((first.lat - origin.lat).abs() < 1e-9 && (first.lon - origin.lon).abs() < 1e-9);
+        assert!(
+            (last.lat - destination.lat).abs() < 1e-9 && (last.lon - destination.lon).abs() < 1e-9
+        );
+
+        // the steps along the path are all the same length
+        let total = haversine(&origin, &destination);
+        for pair in waypoints.windows(2) {
+            let step = haversine(&pair[0], &pair[1]);
+            assert!((step - total / 4.0).abs() < 1e-6);
+        }
+
+        // the great circle bulges poleward of the straight line on a map
+        assert!(waypoints[2].lat > (origin.lat + destination.lat) / 2.0);
+    }
+
+    #[test]
+    fn test_midpoint_bearing() {
+        let new_york = Point {
+            lat: 40.71,
+            lon: -74.01,
+        };
+        // Madrid is almost due east along the parallel, but the great circle sets off north-east
+        let madrid = Point {
+            lat: 40.42,
+            lon: -3.70,
+        };
+        let initial = bearing(&new_york, &madrid);
+        let mid = midpoint_bearing(&new_york, &madrid);
+        assert!((initial - 65.7).abs() < 0.1, "{}", initial);
+        assert!((mid - 90.4).abs() < 0.1, "{}", mid);
+
+        let nearby = Point {
+            lat: 40.72,
+            lon: -73.90,
+        };
+        let (initial, mid) = (
+            bearing(&new_york, &nearby),
+            midpoint_bearing(&new_york, &nearby),
+        );
+        assert!((initial - mid).abs() < 0.1, "{} vs {}", initial, mid);
+    }
+
+    #[test]
+    fn test_elevation_angle() {
+        assert!((elevation_angle(1.0, 1000.0) - 45.0).abs() < 1e-9);
+        assert_eq!(elevation_angle(0.0, 10000.0), 90.0);
+        assert_eq!(elevation_angle(10.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_prefilter_bounds() {
+        // about 111.2 km per degree of latitude on this sphere
+        let dublin = Point {
+            lat: 53.35,
+            lon: -6.26,
+        };
+        let prefilter = Prefilter::new(dublin, 111.2, EARTH_RADIUS_KM);
+        assert!((prefilter.max_dlat - 1.0).abs() < 0.01);
+        // longitude degrees are shorter this far north
+        assert!(prefilter.max_dlon.unwrap() > 1.6);
+
+        let near_pole = Point {
+            lat: 89.5,
+            lon: 0.0,
+        };
+        assert_eq!(
+            Prefilter::new(near_pole, 100.0, EARTH_RADIUS_KM).max_dlon,
+            None
+        );
+
+        let dateline = Point {
+            lat: 0.0,
+            lon: 179.9,
+        };
+        let prefilter = Prefilter::new(dateline, 50.0, EARTH_RADIUS_KM);
+        assert!(prefilter.may_contain(&Point {
+            lat: 0.0,
+            lon: -179.9
+        }));
+        assert!(!prefilter.may_contain(&Point { lat: 0.0, lon: 0.0 }));
+    }
+
+    #[test]
+    fn test_bearing() {
+        let origin = Point { lat: 0.0, lon: 0.0 };
+        let cases = [
+            ((1.0, 0.0), 0.0),
+            ((0.0, 1.0), 90.0),
+            ((-1.0, 0.0), 180.0),
+            ((0.0, -1.0), 270.0),
+        ];
+        for &((lat, lon), expected) in &cases {
+            let b = bearing(&origin, &Point { lat, lon });
+            assert!((b - expected).abs() < 1e-9, "{} != {}", b, expected);
+        }
+    }
+}
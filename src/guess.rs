@@ -0,0 +1,167 @@
+use crate::coords::Point;
+use crate::opensky::OpenskyState;
+use crate::options::Options;
+
+// ground distance at which a plane's closeness score halves
+const CLOSENESS_SCALE_KM: f64 = 10.0;
+// position age at which a plane's recency score halves
+const RECENCY_SCALE_SECS: f64 = 60.0;
+
+// how much each clue counts towards --best-guess
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuessWeights {
+    pub distance: f64,
+    pub elevation: f64,
+    pub recency: f64,
+}
+
+// a plane high in the sky is easier to spot than a nearer one low on the horizon,
+// so elevation counts for the most
+impl Default for GuessWeights {
+    fn default() -> Self {
+        GuessWeights {
+            distance: 1.0,
+            elevation: 2.0,
+            recency: 0.5,
+        }
+    }
+}
+
+impl GuessWeights {
+    // "DISTANCE,ELEVATION,RECENCY", e.g. 1,2,0.5
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let weights = s
+            .split(',')
+            .map(|w| w.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .ok()
+            .filter(|weights| weights.iter().all(|&w| w >= 0.0 && w.is_finite()));
+        match weights.as_deref() {
+            Some(&[distance, elevation, recency]) => Ok(GuessWeights {
+                distance,
+                elevation,
+                recency,
+            }),
+            _ => Err(format!(
+                "Expected three non-negative weights DISTANCE,ELEVATION,RECENCY: {}",
+                s
+            )),
+        }
+    }
+}
+
+// each clue scaled to between 0 and 1, with 1 the most likely
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GuessScore {
+    pub(crate) closeness: f64,
+    pub(crate) elevation: f64,
+    pub(crate) recency: f64,
+    pub(crate) total: f64,
+}
+
+pub(crate) fn guess_score(
+    opts: &Options,
+    observer: &Point,
+    state: &OpenskyState,
+    now: u64,
+) -> GuessScore {
+    let weights = opts.guess_weights;
+    let ground_km = state.position().map_or(f64::INFINITY, |plane_pos| {
+        opts.distance(observer, plane_pos)
+    });
+    let closeness = 1.0 / (1.0 + ground_km / CLOSENESS_SCALE_KM);
+    // planes of unknown altitude might be anywhere in the sky, so they get no credit
+    let elevation = state.elevation(ground_km).unwrap_or(0.0).max(0.0) / 90.0;
+    let seen = state.time_position.unwrap_or(state.last_contact) as u64;
+    let age = now.saturating_sub(seen);
+    let recency = 1.0 / (1.0 + age as f64 / RECENCY_SCALE_SECS);
+
+    GuessScore {
+        closeness,
+        elevation,
+        recency,
+        total: weights.distance * closeness
+            + weights.elevation * elevation
+            + weights.recency * recency,
+    }
+}
+
+// the reasoning behind a score, for --verbose
+pub(crate) fn explain(opts: &Options, state: &OpenskyState, score: &GuessScore) -> String {
+    let weights = opts.guess_weights;
+    format!(
+        "{} scored {:.3} = {} x {:.3} closeness + {} x {:.3} elevation + {} x {:.3} recency",
+        state.icao24,
+        score.total,
+        weights.distance,
+        score.closeness,
+        weights.elevation,
+        score.elevation,
+        weights.recency,
+        score.recency
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::synthetic_state;
+
+    #[test]
+    fn test_overhead_beats_low_and_closer() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        // about 3.3 km north at cruising height, seen 73° up
+        let overhead = OpenskyState {
+            baro_altitude: Some(11_000.0),
+            time_position: Some(1000),
+            ..synthetic_state("overhead", 53.24, -6.18)
+        };
+        // about 2.2 km away, but barely above the rooftops
+        let low = OpenskyState {
+            baro_altitude: Some(150.0),
+            time_position: Some(1000),
+            ..synthetic_state("low", 53.19, -6.18)
+        };
+
+        let opts = Options::default();
+        assert!(
+            opts.distance(&observer, low.position().unwrap())
+                < opts.distance(&observer, overhead.position().unwrap())
+        );
+        let overhead_score = guess_score(&opts, &observer, &overhead, 1010);
+        let low_score = guess_score(&opts, &observer, &low, 1010);
+        assert!(overhead_score.total > low_score.total);
+        assert!(overhead_score.closeness < low_score.closeness);
+
+        // with elevation ignored, nearest wins again
+        let opts = Options {
+            guess_weights: GuessWeights {
+                elevation: 0.0,
+                ..GuessWeights::default()
+            },
+            ..Options::default()
+        };
+        assert!(
+            guess_score(&opts, &observer, &overhead, 1010).total
+                < guess_score(&opts, &observer, &low, 1010).total
+        );
+    }
+
+    #[test]
+    fn test_parse_weights() {
+        assert_eq!(
+            GuessWeights::parse("1, 3,0").unwrap(),
+            GuessWeights {
+                distance: 1.0,
+                elevation: 3.0,
+                recency: 0.0
+            }
+        );
+        for bad in ["1,2", "1,2,3,4", "1,-2,3", "a,b,c"] {
+            assert!(GuessWeights::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::coords::Point;
+use crate::geo::{haversine, EARTH_RADIUS_KM};
+use crate::opensky::OpenskyState;
+use std::collections::HashMap;
+
+// size of each grid cell, in degrees of latitude and longitude
+const CELL_DEG: f64 = 1.0;
+const ROWS: i32 = (180.0 / CELL_DEG) as i32;
+const COLS: i32 = (360.0 / CELL_DEG) as i32;
+
+/// Plane states bucketed into a lat/lon grid, for answering nearest-plane queries from
+/// many observers against one fetched set of states without scanning all of them.
+pub struct StateIndex<'a> {
+    cells: HashMap<(i32, i32), Vec<(Point, &'a OpenskyState)>>,
+}
+
+/// Builds an index over the states that have a position.
+pub fn build_index(states: &[OpenskyState]) -> StateIndex<'_> {
+    let mut cells: HashMap<(i32, i32), Vec<(Point, &OpenskyState)>> = HashMap::new();
+    for state in states {
+        if let Some(plane_pos) = state.position() {
+            cells
+                .entry(cell(&plane_pos))
+                .or_default()
+                .push((plane_pos, state));
+        }
+    }
+    StateIndex { cells }
+}
+
+fn cell(p: &Point) -> (i32, i32) {
+    let row = ((p.lat + 90.0) / CELL_DEG).floor() as i32;
+    let col = ((p.lon + 180.0) / CELL_DEG).floor() as i32;
+    (row.clamp(0, ROWS - 1), col.rem_euclid(COLS))
+}
+
+impl<'a> StateIndex<'a> {
+    /// The nearest plane and its great-circle distance in km, as `find_nearest` would give
+    /// with default options: no filters, on a sphere of `EARTH_RADIUS_KM`, with ties going
+    /// to the lowest `icao24`.
+    pub fn nearest(&self, observer: &Point) -> Option<(f64, &'a OpenskyState)> {
+        let (row0, col0) = cell(observer);
+        let mut best: Option<(f64, &'a OpenskyState)> = None;
+        let beats = |distance: f64, state: &OpenskyState, best: &Option<(f64, &OpenskyState)>| {
+            best.is_none_or(|(d, s)| (distance, &state.icao24) < (d, &s.icao24))
+        };
+
+        // rows outward from the observer's, until even the latitude gap alone is too far
+        for dr in 0..ROWS {
+            let mut any_row = false;
+            let sides = if dr == 0 { 1 } else { 2 };
+            for &row in [row0 - dr, row0 + dr].iter().take(sides) {
+                if !(0..ROWS).contains(&row) {
+                    continue;
+                }
+                let lat_gap = lat_gap_km(observer, row);
+                if best.is_some_and(|(d, _)| lat_gap > d) {
+                    continue;
+                }
+                any_row = true;
+
+                for col in 0..COLS {
+                    let bound = lat_gap.max(lon_gap_km(observer, col0, col));
+                    if best.is_some_and(|(d, _)| bound > d) {
+                        continue;
+                    }
+                    for &(plane_pos, state) in self.cells.get(&(row, col)).into_iter().flatten() {
+                        let distance = haversine(observer, &plane_pos);
+                        if beats(distance, state, &best) {
+                            best = Some((distance, state));
+                        }
+                    }
+                }
+            }
+            if !any_row && best.is_some() {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+// the least distance from the observer to any point in the row's band of latitude
+fn lat_gap_km(observer: &Point, row: i32) -> f64 {
+    let south = row as f64 * CELL_DEG - 90.0;
+    let north = south + CELL_DEG;
+    let gap = (south - observer.lat).max(observer.lat - north).max(0.0);
+    gap.to_radians() * EARTH_RADIUS_KM
+}
+
+// a lower bound on the distance to any point in a column: the distance from the observer
+// to the great circle through its nearest or furthest meridian, whichever is closer
+fn lon_gap_km(observer: &Point, col0: i32, col: i32) -> f64 {
+    let dc = (col - col0)
+        .rem_euclid(COLS)
+        .min((col0 - col).rem_euclid(COLS));
+    if dc <= 1 {
+        return 0.0;
+    }
+    let near = ((dc - 1) as f64 * CELL_DEG).to_radians();
+    let far = ((dc + 1) as f64 * CELL_DEG)
+        .to_radians()
+        .min(std::f64::consts::PI);
+    let sin_gap = near.sin().min(far.sin()).max(0.0);
+    (observer.lat.to_radians().cos() * sin_gap).asin() * EARTH_RADIUS_KM
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_nearest;
+    use crate::options::Options;
+    use crate::test_util::synthetic_state;
+
+    #[test]
+    fn test_index_matches_linear_scan() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut states: Vec<OpenskyState> = (0..2000)
+            .map(|i| {
+                let lat = next() * 180.0 - 90.0;
+                let lon = next() * 360.0 - 180.0;
+                synthetic_state(&format!("{:06x}", i), lat, lon)
+            })
+            .collect();
+        // a dense patch, near the antimeridian, the poles, and one equally distant pair
+        for (i, &(lat, lon)) in [
+            (53.42, -6.27),
+            (53.43, -6.25),
+            (0.5, 179.99),
+            (0.5, -179.99),
+            (89.9, 10.0),
+            (89.9, -170.0),
+            (-89.95, 45.0),
+            (10.0, 20.1),
+            (10.0, 19.9),
+        ]
+        .iter()
+        .enumerate()
+        {
+            states.push(synthetic_state(&format!("x{}", i), lat, lon));
+        }
+        states.push(synthetic_state("nowhere", 0.0, 0.0));
+        states.last_mut().unwrap().latitude = None;
+
+        let index = build_index(&states);
+        let mut observers: Vec<Point> = (0..40)
+            .map(|_| Point {
+                lat: next() * 180.0 - 90.0,
+                lon: next() * 360.0 - 180.0,
+            })
+            .collect();
+        observers.extend([
+            Point {
+                lat: 53.21,
+                lon: -6.18,
+            },
+            Point {
+                lat: 0.5,
+                lon: 180.0,
+            },
+            Point {
+                lat: 90.0,
+                lon: 0.0,
+            },
+            Point {
+                lat: -90.0,
+                lon: 0.0,
+            },
+            Point {
+                lat: 10.0,
+                lon: 20.0,
+            },
+        ]);
+
+        for observer in &observers {
+            let expected = find_nearest(observer, &states, &Options::default(), None);
+            let found = index.nearest(observer);
+            assert_eq!(
+                found.map(|(d, s)| (d, &s.icao24)),
+                expected.map(|(d, s)| (d, &s.icao24)),
+                "{:?}",
+                observer
+            );
+        }
+
+        assert!(build_index(&[]).nearest(&observers[0]).is_none());
+    }
+}
@@ -0,0 +1,1329 @@
+mod busyness;
+mod coords;
+mod credentials;
+mod error;
+mod fence;
+mod geo;
+mod guess;
+mod index;
+mod locale;
+mod opensky;
+mod options;
+mod output;
+mod places;
+mod pluscode;
+mod sbs;
+#[cfg(test)]
+mod test_util;
+mod timestamps;
+
+pub use coords::{CoordFormat, Point};
+pub use credentials::Credentials;
+pub use error::Error;
+pub use fence::Fence;
+pub use geo::{bearing, haversine, haversine_with_radius, EARTH_RADIUS_KM};
+pub use guess::GuessWeights;
+pub use index::{build_index, StateIndex};
+pub use locale::NumberLocale;
+pub use opensky::{BoundingBox, OpenskyApi, OpenskyResponse, OpenskyState, PlaneSource};
+pub use options::{
+    ArrowSource, Options, OutputFormat, SortKey, SortOrder, SpeedUnits, Units, USAGE,
+};
+pub use sbs::SbsSource;
+pub use timestamps::TimeZoneChoice;
+
+use busyness::{busyness_score, DEFAULT_BUSYNESS_POWER, DEFAULT_BUSYNESS_RADIUS_KM};
+use guess::{explain, guess_score};
+use opensky::{
+    fetch_with_min_results, validate_response, Failover, MinInterval, RateLimited,
+    ACCOUNT_MIN_INTERVAL_SECS, ANONYMOUS_MIN_INTERVAL_SECS, DEFAULT_SEARCH_KM,
+};
+use options::json_request_args;
+use output::{
+    altitude_band_counts, histogram, render_altitude_bands, render_comparison, render_histogram,
+    render_nearest_place, render_radar, render_result, render_validation, write_output, Sighting,
+    WatchStatus,
+};
+use places::{nearest_place, parse_places};
+use sbs::{DEFAULT_SBS_PORT, DEFAULT_SBS_WINDOW_SECS};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// --histogram bands, in the display units
+const DEFAULT_BIN_WIDTH: f64 = 10.0;
+
+/// Runs the command line tool with the given arguments.
+pub fn run<I: Iterator<Item = String>>(args: I) -> Result<(), Error> {
+    let args = with_json_request(args.collect())?;
+    let opts = Options::from_args(args.into_iter())?;
+
+    if let Some(path) = &opts.validate {
+        return report_validation(path);
+    }
+
+    let (label, p) = match opts.observer() {
+        Some(observer) => (None, observer.map_err(Error::Coords)?),
+        None => {
+            // read coords from stdin
+            let mut coords = String::new();
+            io::stdin()
+                .read_to_string(&mut coords)
+                .map_err(|e| Error::Coords(format!("Failed to read input coords: {}", e)))?;
+            Point::from_labeled_coords(&coords, opts.input_format)?
+        }
+    };
+
+    if let Some(path) = &opts.nearest_airport {
+        return report_nearest_place(&p, path, &opts);
+    }
+
+    let source = plane_source(&opts);
+    if let (Some(second), Some(icao24)) = (opts.compare, &opts.track) {
+        let observers = [(label.as_deref(), p), (None, second)];
+        return report_comparison(&opts, &observers, icao24, source.as_ref());
+    }
+    match opts.watch {
+        None => empty_ok(
+            &opts,
+            report(&opts, &p, label.as_deref(), source.as_ref(), None),
+        ),
+        Some(interval) if opts.watch_until_radius.is_some() => {
+            watch_until(&opts, &p, label.as_deref(), source.as_ref(), interval)
+        }
+        Some(interval) if opts.watch_diff => {
+            watch_diff(&opts, &p, label.as_deref(), source.as_ref(), interval)
+        }
+        Some(interval) => {
+            let mut listed = None;
+            loop {
+                // a bad tick, like an API hiccup or an empty sky, shouldn't end the watch
+                let watch = Some(&mut listed).filter(|_| opts.table);
+                if let Err(e) = report(&opts, &p, label.as_deref(), source.as_ref(), watch) {
+                    eprintln!("{}", e);
+                }
+                thread::sleep(Duration::from_secs(interval));
+            }
+        }
+    }
+}
+
+/// The arguments with a `--stdin-json` request read from stdin and merged in, in place of
+/// the flag itself, so the request is checked and acted on exactly like the command line.
+/// Arguments without the flag come back unchanged.
+pub fn with_json_request(mut args: Vec<String>) -> Result<Vec<String>, Error> {
+    if !Options::from_args(args.iter().cloned())?.stdin_json {
+        return Ok(args);
+    }
+
+    // the request takes the place of coordinates on stdin, and wins over the flags
+    let mut request = String::new();
+    io::stdin()
+        .read_to_string(&mut request)
+        .map_err(|e| Error::Config(format!("Failed to read JSON request: {}", e)))?;
+    args.extend(json_request_args(&request)?);
+    args.retain(|arg| arg != "--stdin-json");
+    if Options::from_args(args.iter().cloned())?
+        .observer()
+        .is_none()
+    {
+        return Err(Error::Config(
+            "The JSON request needs lat and lon, or a bbox".to_string(),
+        ));
+    }
+    Ok(args)
+}
+
+/// Whether an error from `run` with these arguments should be written as JSON on stdout;
+/// pass them through `with_json_request` first, as a request can ask for JSON too.
+pub fn json_errors(args: &[String]) -> bool {
+    let json = OutputFormat::from_name("json");
+    match Options::from_args(args.iter().cloned()) {
+        Ok(opts) => json == Ok(opts.format),
+        // the arguments may be what failed to parse, so look for the flag directly
+        Err(_) => {
+            json.is_ok()
+                && (args.iter().any(|arg| arg == "--json")
+                    || args
+                        .windows(2)
+                        .any(|pair| pair[0] == "--format" && pair[1] == "json"))
+        }
+    }
+}
+
+// OpenSky, or with --api-urls each of those mirrors before it
+fn plane_source(opts: &Options) -> Box<dyn PlaneSource> {
+    if let Some(addr) = &opts.sbs {
+        // a local receiver has no rate limit to respect
+        let addr = if addr.contains(':') {
+            addr.clone()
+        } else {
+            format!("{}:{}", addr, DEFAULT_SBS_PORT)
+        };
+        return Box::new(SbsSource {
+            addr,
+            window: Duration::from_secs(opts.sbs_window.unwrap_or(DEFAULT_SBS_WINDOW_SECS)),
+        });
+    }
+
+    let env = OpenskyApi::from_env();
+    let credentials = opts.credentials.clone().or(env.credentials);
+    // each endpoint keeps its own limit, so a failed mirror doesn't hold up the next one
+    let min_interval = opts.min_interval.unwrap_or(if credentials.is_some() {
+        ACCOUNT_MIN_INTERVAL_SECS
+    } else {
+        ANONYMOUS_MIN_INTERVAL_SECS
+    });
+    let api = |url: String| -> Box<dyn PlaneSource> {
+        Box::new(RateLimited {
+            source: Box::new(OpenskyApi {
+                url,
+                extended: !opts.categories.is_empty(),
+                credentials: credentials.clone(),
+            }),
+            limiter: MinInterval::new(Duration::from_secs_f64(min_interval)),
+        })
+    };
+    if opts.api_urls.is_empty() {
+        return api(env.url);
+    }
+
+    let mut sources: Vec<(String, Box<dyn PlaneSource>)> = opts
+        .api_urls
+        .iter()
+        .map(|url| (url.clone(), api(url.clone())))
+        .collect();
+    sources.push((env.url.clone(), api(env.url)));
+
+    Box::new(Failover { sources })
+}
+
+// with --empty-ok, an empty sky is reported but isn't a failure
+fn empty_ok(opts: &Options, result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(Error::NoResults) if opts.empty_ok => {
+            eprintln!("{}", Error::NoResults);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum WatchStep {
+    Found,
+    Continue,
+    TimedOut,
+}
+
+// whether --watch-until-radius should stop after a poll whose nearest plane was this
+// far away, in display units; it gives up rather than poll again past the deadline
+fn watch_step(
+    nearest: Option<f64>,
+    until_radius: f64,
+    elapsed: Duration,
+    interval: Duration,
+    timeout: Option<Duration>,
+) -> WatchStep {
+    if nearest.is_some_and(|distance| distance <= until_radius) {
+        WatchStep::Found
+    } else if timeout.is_some_and(|timeout| elapsed + interval > timeout) {
+        WatchStep::TimedOut
+    } else {
+        WatchStep::Continue
+    }
+}
+
+// poll quietly until a plane comes within --watch-until-radius, then report it
+fn watch_until(
+    opts: &Options,
+    p: &Point,
+    label: Option<&str>,
+    source: &dyn PlaneSource,
+    interval: u64,
+) -> Result<(), Error> {
+    let until_radius = opts.watch_until_radius.unwrap_or(0.0);
+    let interval = Duration::from_secs(interval);
+    let timeout = opts.watch_timeout.map(Duration::from_secs);
+    let started = Instant::now();
+    loop {
+        let states = fetch_states(opts, p, source).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Vec::new()
+        });
+        let results = nearest_planes(p, &states, opts, None);
+        let nearest = results
+            .first()
+            .map(|&(distance, _)| opts.units.distance_from_km(distance));
+        match watch_step(nearest, until_radius, started.elapsed(), interval, timeout) {
+            WatchStep::Found => return write_results(opts, p, label, results, None),
+            WatchStep::TimedOut => {
+                return Err(Error::WatchTimeout(opts.watch_timeout.unwrap_or(0)))
+            }
+            WatchStep::Continue => thread::sleep(interval),
+        }
+    }
+}
+
+// the nearest plane's icao24 and distance in display units at a --watch-diff poll, or
+// None for an empty sky
+type Nearest = Option<(String, f64)>;
+
+// whether a --watch-diff poll is worth printing: the first always is, then only when a
+// different plane is nearest, the sky empties or fills, or the distance crosses the
+// threshold either way
+fn watch_changed(last: Option<&Nearest>, current: &Nearest, threshold: Option<f64>) -> bool {
+    let last = match last {
+        Some(last) => last,
+        None => return true,
+    };
+    match (last, current) {
+        (Some((last_icao24, last_distance)), Some((icao24, distance))) => {
+            last_icao24 != icao24
+                || threshold.is_some_and(|t| (*last_distance <= t) != (*distance <= t))
+        }
+        (None, None) => false,
+        _ => true,
+    }
+}
+
+// the planes a --watch --table poll listed, kept to compare the next poll's with; None
+// before the first poll
+type Listed = Option<Vec<(f64, OpenskyState)>>;
+
+// how a --watch --table poll's planes compare with the last poll's, by icao24: a status for
+// each plane listed now, and the positions in the last poll of the planes that have gone
+fn diff_polls(last: &[&str], current: &[&str]) -> (Vec<WatchStatus>, Vec<usize>) {
+    let last_set: HashSet<&str> = last.iter().copied().collect();
+    let current_set: HashSet<&str> = current.iter().copied().collect();
+
+    let statuses = current
+        .iter()
+        .map(|icao24| {
+            if last_set.contains(icao24) {
+                WatchStatus::Continuing
+            } else {
+                WatchStatus::New
+            }
+        })
+        .collect();
+    let departed = last
+        .iter()
+        .enumerate()
+        .filter(|(_, icao24)| !current_set.contains(*icao24))
+        .map(|(i, _)| i)
+        .collect();
+    (statuses, departed)
+}
+
+// like a plain --watch, but quiet until the nearest plane changes
+fn watch_diff(
+    opts: &Options,
+    p: &Point,
+    label: Option<&str>,
+    source: &dyn PlaneSource,
+    interval: u64,
+) -> Result<(), Error> {
+    let mut last: Option<Nearest> = None;
+    loop {
+        match fetch_states(opts, p, source) {
+            Ok(states) => {
+                let results = nearest_planes(p, &states, opts, None);
+                let nearest = results.first().map(|&(distance, state)| {
+                    (state.icao24.clone(), opts.units.distance_from_km(distance))
+                });
+                if watch_changed(last.as_ref(), &nearest, opts.watch_diff_threshold) {
+                    if let Err(e) = write_results(opts, p, label, results, None) {
+                        eprintln!("{}", e);
+                    }
+                }
+                last = Some(nearest);
+            }
+            // an API hiccup says nothing about the sky, so it isn't a change
+            Err(e) => eprintln!("{}", e),
+        }
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+// call Opensky API and parse states
+fn fetch_states(
+    opts: &Options,
+    p: &Point,
+    source: &dyn PlaneSource,
+) -> Result<Vec<OpenskyState>, Error> {
+    match opts.min_results {
+        Some(min_results) => {
+            let bbox = opts
+                .bbox
+                .or_else(|| BoundingBox::around(p, DEFAULT_SEARCH_KM));
+            fetch_with_min_results(source, bbox, min_results)
+        }
+        None => source.fetch(opts.bbox.as_ref()),
+    }
+}
+
+// one fetch and one result, which --watch repeats
+fn report(
+    opts: &Options,
+    p: &Point,
+    label: Option<&str>,
+    source: &dyn PlaneSource,
+    watch: Option<&mut Listed>,
+) -> Result<(), Error> {
+    let states = fetch_states(opts, p, source)?;
+
+    if opts.any {
+        let found = any_plane_within(p, &states, opts);
+        println!("{}", found);
+        return if found {
+            Ok(())
+        } else {
+            Err(Error::NoneWithinRadius)
+        };
+    }
+
+    let results = nearest_planes(p, &states, opts, None);
+    write_results(opts, p, label, results, watch)
+}
+
+// the --track plane as seen from each --compare observer, side by side
+fn report_comparison(
+    opts: &Options,
+    observers: &[(Option<&str>, Point)],
+    icao24: &str,
+    source: &dyn PlaneSource,
+) -> Result<(), Error> {
+    let states = fetch_states(opts, &observers[0].1, source)?;
+    let points: Vec<Point> = observers.iter().map(|&(_, p)| p).collect();
+    let sightings = nearest_from_each(&points, &states, opts);
+    if sightings.iter().all(Option::is_none) {
+        return Err(Error::NotVisible(icao24.to_string()));
+    }
+
+    let rendered = render_comparison(opts, observers, &sightings);
+    write_output(opts.output.as_deref(), opts.append, &rendered).map_err(Error::Output)
+}
+
+// the nearest plane passing the filters from each of several observers, if any
+fn nearest_from_each<'a>(
+    observers: &[Point],
+    states: &'a [OpenskyState],
+    opts: &Options,
+) -> Vec<Option<(f64, &'a OpenskyState)>> {
+    observers
+        .iter()
+        .map(|observer| find_nearest(observer, states, opts, None))
+        .collect()
+}
+
+// the histogram or the closest planes, whichever was asked for
+fn write_results(
+    opts: &Options,
+    p: &Point,
+    label: Option<&str>,
+    mut results: Vec<(f64, &OpenskyState)>,
+    watch: Option<&mut Listed>,
+) -> Result<(), Error> {
+    // take the closest ones and tell us about them
+    eprintln!("Plane states with known coordinates: {}", results.len());
+    if let Some(warning) = slant_range_warning(opts, &results) {
+        eprintln!("{}", warning);
+    }
+    if opts.busyness {
+        eprintln!("{}", busyness_line(opts, &results));
+    }
+    let visible = results.len();
+    if results.is_empty() {
+        // the planes listed last poll still get their one departed row in an otherwise
+        // empty table, and the next poll starts afresh
+        if let Some(last) = watch.and_then(|listed| listed.replace(Vec::new())) {
+            if !last.is_empty() {
+                let mut sightings = Vec::new();
+                add_departures(opts, p, label, &mut sightings, &last, reference_time(&[]));
+                let rendered = render_result(opts, &sightings, 0);
+                write_output(opts.output.as_deref(), opts.append, &rendered)
+                    .map_err(Error::Output)?;
+            }
+        }
+        // an empty sky is still worth a scrape: the visible gauge drops to zero
+        #[cfg(feature = "formats")]
+        if opts.format == OutputFormat::Metrics {
+            write_output(
+                opts.output.as_deref(),
+                opts.append,
+                &render_result(opts, &[], 0),
+            )
+            .map_err(Error::Output)?;
+        }
+        return Err(Error::NoResults);
+    }
+
+    if opts.histogram {
+        let bin_width = opts.bin_width.unwrap_or(DEFAULT_BIN_WIDTH);
+        let distances: Vec<f64> = results
+            .iter()
+            .map(|&(distance, _)| opts.units.distance_from_km(distance))
+            .collect();
+        let rendered = render_histogram(opts, &histogram(&distances, bin_width), bin_width);
+        return write_output(opts.output.as_deref(), opts.append, &rendered).map_err(Error::Output);
+    }
+
+    if opts.count_by_altitude_band {
+        let rendered = render_altitude_bands(opts, &altitude_band_counts(opts, &results));
+        return write_output(opts.output.as_deref(), opts.append, &rendered).map_err(Error::Output);
+    }
+
+    if opts.radar {
+        let rendered = render_radar(opts, p, &results);
+        return write_output(opts.output.as_deref(), opts.append, &rendered).map_err(Error::Output);
+    }
+
+    let now = reference_time(&results);
+
+    if opts.best_guess {
+        // the most likely planes first, which --sort-by doesn't reshuffle
+        let mut scored: Vec<_> = results
+            .into_iter()
+            .map(|result| (guess_score(opts, p, result.1, now), result))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total.total_cmp(&a.total));
+        scored.truncate(opts.count.unwrap_or(1));
+        if opts.verbose {
+            for (score, (_, state)) in &scored {
+                eprintln!("{}", explain(opts, state, score));
+            }
+        }
+        results = scored.into_iter().map(|(_, result)| result).collect();
+    } else {
+        results.truncate(opts.count.unwrap_or(1));
+        order_results(&mut results, opts.sort_by, opts.order);
+    }
+
+    // with --watch --table, each poll replaces the last one's list
+    let current: Vec<(f64, OpenskyState)> = results
+        .iter()
+        .map(|&(distance, state)| (distance, state.clone()))
+        .collect();
+    let last = watch.and_then(|listed| listed.replace(current));
+
+    let mut sightings: Vec<Sighting> = results
+        .into_iter()
+        .map(|(distance, state)| Sighting {
+            observer_label: label,
+            ..Sighting::new(opts, p, state, distance, now)
+        })
+        .collect();
+    if let Some(last) = &last {
+        add_departures(opts, p, label, &mut sightings, last, now);
+    }
+    let rendered = render_result(opts, &sightings, visible);
+
+    write_output(opts.output.as_deref(), opts.append, &rendered).map_err(Error::Output)
+}
+
+// marks this --watch --table poll's sightings against the last poll's list; planes that
+// have gone stay listed for this one poll, at their last distance
+fn add_departures<'a>(
+    opts: &Options,
+    p: &Point,
+    label: Option<&'a str>,
+    sightings: &mut Vec<Sighting<'a>>,
+    last: &'a [(f64, OpenskyState)],
+    now: u64,
+) {
+    let last_icao24s: Vec<&str> = last.iter().map(|(_, s)| s.icao24.as_str()).collect();
+    let icao24s: Vec<&str> = sightings.iter().map(|s| s.state.icao24.as_str()).collect();
+    let (statuses, departed) = diff_polls(&last_icao24s, &icao24s);
+    for (sighting, status) in sightings.iter_mut().zip(statuses) {
+        sighting.watch_status = Some(status);
+    }
+    sightings.extend(departed.into_iter().map(|i| Sighting {
+        observer_label: label,
+        watch_status: Some(WatchStatus::Departed),
+        ..Sighting::new(opts, p, &last[i].1, last[i].0, now)
+    }));
+}
+
+// the --validate mode, for checking saved responses such as test fixtures; any surprise
+// fails the run, so drift in the API can stop a build
+fn report_validation(path: &str) -> Result<(), Error> {
+    let data =
+        fs::read(path).map_err(|e| Error::Config(format!("Couldn't read {}: {}", path, e)))?;
+    let validation = validate_response(data)?;
+    print!("{}", render_validation(&validation));
+
+    match validation.surprises.len() {
+        0 => Ok(()),
+        n => Err(Error::Config(format!("{} schema surprises in {}", n, path))),
+    }
+}
+
+// the --nearest-airport mode, which doesn't need OpenSky at all
+fn report_nearest_place(observer: &Point, path: &str, opts: &Options) -> Result<(), Error> {
+    let places = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_places(&text))
+        .map_err(|e| Error::Config(format!("Couldn't read places from {}: {}", path, e)))?;
+
+    let (distance, place) = nearest_place(observer, &places, opts)
+        .ok_or_else(|| Error::Config(format!("No places listed in {}", path)))?;
+    print!("{}", render_nearest_place(opts, place, distance));
+
+    Ok(())
+}
+
+/// Every plane passing the filters in `opts` and the optional `predicate`, paired with its
+/// distance in km from the observer and sorted nearest first.
+///
+/// The predicate sees each state before any distances are worked out, so it can express
+/// filtering the command line options can't. `None` means no extra filtering.
+pub fn nearest_planes<'a>(
+    observer: &Point,
+    states: &'a [OpenskyState],
+    opts: &Options,
+    predicate: Option<&dyn Fn(&OpenskyState) -> bool>,
+) -> Vec<(f64, &'a OpenskyState)> {
+    // calculate distances to each plane, skipping the trigonometry for any plane that
+    // can't be within --radius anyway
+    let prefilter = opts.prefilter(observer);
+    let candidates = states
+        .iter()
+        .filter(|state| opts.accepts(state))
+        .filter(|state| predicate.is_none_or(|keep| keep(state)))
+        .flat_map(|state| {
+            state
+                .position()
+                .filter(|plane_pos| prefilter.is_none_or(|pf| pf.may_contain(plane_pos)))
+                .map(|plane_pos| (opts.distance(observer, plane_pos), state))
+        })
+        .filter(|&(ground_km, state)| opts.within_view(ground_km, state))
+        .map(|(ground_km, state)| (opts.range(ground_km, state), state));
+    let mut results = match opts.max_planes {
+        Some(max_planes) => nearest_n(candidates, max_planes),
+        None => candidates.collect(),
+    };
+
+    results.sort_unstable_by(by_distance);
+    results
+}
+
+// order by distance from the requested point, breaking ties by icao24 so that equally
+// distant planes always come out in the same order
+fn by_distance(a: &(f64, &OpenskyState), b: &(f64, &OpenskyState)) -> Ordering {
+    a.0.total_cmp(&b.0)
+        .then_with(|| a.1.icao24.cmp(&b.1.icao24))
+}
+
+// a candidate in the --max-planes heap, which keeps the furthest one on top
+struct Candidate<'a>((f64, &'a OpenskyState));
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        by_distance(&self.0, &other.0)
+    }
+}
+
+// the n nearest planes, in no particular order, holding at most n + 1 at a time
+fn nearest_n<'a>(
+    candidates: impl Iterator<Item = (f64, &'a OpenskyState)>,
+    n: usize,
+) -> Vec<(f64, &'a OpenskyState)> {
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for candidate in candidates {
+        heap.push(Candidate(candidate));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_iter().map(|Candidate(c)| c).collect()
+}
+
+/// The single nearest plane, chosen as in [`nearest_planes`].
+pub fn find_nearest<'a>(
+    observer: &Point,
+    states: &'a [OpenskyState],
+    opts: &Options,
+    predicate: Option<&dyn Fn(&OpenskyState) -> bool>,
+) -> Option<(f64, &'a OpenskyState)> {
+    nearest_planes(observer, states, opts, predicate)
+        .into_iter()
+        .next()
+}
+
+// the "now" that ages are measured against: OpenSky's snapshot time when the response
+// gave one, as the local clock may be skewed, or else the local clock
+fn reference_time(results: &[(f64, &OpenskyState)]) -> u64 {
+    results
+        .iter()
+        .filter_map(|(_, state)| state.response_time)
+        .max()
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        })
+}
+
+// owns up to how many of the results --slant-range couldn't apply to
+fn slant_range_warning(opts: &Options, results: &[(f64, &OpenskyState)]) -> Option<String> {
+    if !opts.slant_range {
+        return None;
+    }
+
+    let missing = results
+        .iter()
+        .filter(|(_, state)| state.altitude().is_none())
+        .count();
+    if missing == 0 {
+        return None;
+    }
+
+    Some(format!(
+        "slant range: {} of {} planes lacked altitude, using ground distance",
+        missing,
+        results.len()
+    ))
+}
+
+// the --busyness summary, e.g. "Busyness: 23/100 (4 planes within 50.0 km)"
+fn busyness_line(opts: &Options, results: &[(f64, &OpenskyState)]) -> String {
+    let radius_km = opts
+        .busyness_radius
+        .or(opts.radius)
+        .map_or(DEFAULT_BUSYNESS_RADIUS_KM, |radius| {
+            opts.units.distance_to_km(radius)
+        });
+    let distances: Vec<f64> = results.iter().map(|&(distance, _)| distance).collect();
+    let power = opts.busyness_power.unwrap_or(DEFAULT_BUSYNESS_POWER);
+    let within = distances.iter().filter(|&&d| d < radius_km).count();
+
+    format!(
+        "Busyness: {:.0}/100 ({} {} within {:.1} {})",
+        busyness_score(&distances, radius_km, power),
+        within,
+        if within == 1 { "plane" } else { "planes" },
+        opts.units.distance_from_km(radius_km),
+        opts.units.distance_label()
+    )
+}
+
+// reorders results already chosen by distance; a stable sort keeps nearer planes first
+// among equals, and planes missing the sort value go last whichever the order
+fn order_results(results: &mut [(f64, &OpenskyState)], key: SortKey, order: SortOrder) {
+    fn callsign(state: &OpenskyState) -> Option<&str> {
+        Some(state.callsign.trim()).filter(|c| !c.is_empty())
+    }
+
+    results.sort_by(|(d1, s1), (d2, s2)| match key {
+        SortKey::Distance => missing_last(Some(d1), Some(d2), order),
+        SortKey::Altitude => missing_last(s1.altitude(), s2.altitude(), order),
+        SortKey::Speed => missing_last(s1.velocity, s2.velocity, order),
+        SortKey::Callsign => missing_last(callsign(s1), callsign(s2), order),
+    });
+}
+
+fn missing_last<T: PartialOrd>(a: Option<T>, b: Option<T>, order: SortOrder) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            match order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// stops at the first matching plane rather than measuring them all
+fn any_plane_within(observer: &Point, states: &[OpenskyState], opts: &Options) -> bool {
+    states
+        .iter()
+        .filter(|state| opts.accepts(state))
+        .any(|state| {
+            state.position().is_some_and(|plane_pos| {
+                opts.within_view(opts.distance(observer, plane_pos), state)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::synthetic_state;
+
+    #[test]
+    fn test_any_plane_within() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        let opts = Options {
+            any: true,
+            radius: Some(5.0),
+            ..Options::default()
+        };
+
+        // roughly 11 km and 55 km north of the observer, plus one without a position
+        let far = vec![
+            synthetic_state("a", 53.31, -6.18),
+            synthetic_state("b", 53.71, -6.18),
+            OpenskyState {
+                latitude: None,
+                ..synthetic_state("c", 53.21, -6.18)
+            },
+        ];
+        assert!(!any_plane_within(&observer, &far, &opts));
+
+        let mut near = far;
+        near.push(synthetic_state("d", 53.23, -6.17));
+        assert!(any_plane_within(&observer, &near, &opts));
+
+        // filters still apply before the radius check
+        let opts = Options {
+            min_speed: Some(100.0),
+            ..opts
+        };
+        assert!(!any_plane_within(&observer, &near, &opts));
+    }
+
+    #[test]
+    fn test_predicate_filter() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        let states = vec![
+            OpenskyState {
+                origin_country: "United Kingdom".to_string(),
+                ..synthetic_state("near", 53.22, -6.18)
+            },
+            OpenskyState {
+                origin_country: "Ireland".to_string(),
+                ..synthetic_state("middle", 53.5, -6.18)
+            },
+            OpenskyState {
+                origin_country: "Ireland".to_string(),
+                ..synthetic_state("far", 54.0, -6.18)
+            },
+        ];
+        let opts = Options::default();
+
+        let (_, nearest) = find_nearest(&observer, &states, &opts, None).unwrap();
+        assert_eq!(nearest.icao24, "near");
+
+        let irish_only = |state: &OpenskyState| state.origin_country == "Ireland";
+        let results = nearest_planes(&observer, &states, &opts, Some(&irish_only));
+        let kept: Vec<&str> = results.iter().map(|(_, s)| s.icao24.as_str()).collect();
+        assert_eq!(kept, vec!["middle", "far"]);
+
+        let nobody = |_: &OpenskyState| false;
+        assert!(find_nearest(&observer, &states, &opts, Some(&nobody)).is_none());
+    }
+
+    #[test]
+    fn test_order_results() {
+        let states = [
+            OpenskyState {
+                callsign: "RYR1A   ".to_string(),
+                baro_altitude: Some(3000.0),
+                ..synthetic_state("a", 53.3, -6.2)
+            },
+            OpenskyState {
+                callsign: "".to_string(),
+                baro_altitude: Some(11000.0),
+                ..synthetic_state("b", 53.4, -6.2)
+            },
+            OpenskyState {
+                callsign: "AAL205  ".to_string(),
+                ..synthetic_state("c", 53.5, -6.2)
+            },
+            OpenskyState {
+                callsign: "EIN12   ".to_string(),
+                geo_altitude: Some(7000.0),
+                ..synthetic_state("d", 53.6, -6.2)
+            },
+        ];
+        let nearest_first = || {
+            states
+                .iter()
+                .enumerate()
+                .map(|(i, state)| (i as f64, state))
+                .collect::<Vec<_>>()
+        };
+        let order = |results: &[(f64, &OpenskyState)]| {
+            results
+                .iter()
+                .map(|(_, s)| s.icao24.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut results = nearest_first();
+        order_results(&mut results, SortKey::Altitude, SortOrder::Asc);
+        assert_eq!(order(&results), vec!["a", "d", "b", "c"]);
+        order_results(&mut results, SortKey::Altitude, SortOrder::Desc);
+        assert_eq!(order(&results), vec!["b", "d", "a", "c"]);
+
+        let mut results = nearest_first();
+        order_results(&mut results, SortKey::Callsign, SortOrder::Asc);
+        assert_eq!(order(&results), vec!["c", "d", "a", "b"]);
+        order_results(&mut results, SortKey::Callsign, SortOrder::Desc);
+        assert_eq!(order(&results), vec!["a", "d", "c", "b"]);
+
+        // no speeds at all leaves the distance order alone
+        let mut results = nearest_first();
+        order_results(&mut results, SortKey::Speed, SortOrder::Desc);
+        assert_eq!(order(&results), vec!["a", "b", "c", "d"]);
+        order_results(&mut results, SortKey::Distance, SortOrder::Desc);
+        assert_eq!(order(&results), vec!["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_equal_distance_tiebreak() {
+        let observer = Point { lat: 0.0, lon: 0.0 };
+        // the same spot twice, plus one due south at the same distance as those due north
+        let states = [
+            synthetic_state("4ca2b1", 0.1, 0.0),
+            synthetic_state("4ca2b0", 0.1, 0.0),
+            synthetic_state("3c6444", -0.1, 0.0),
+            synthetic_state("a00001", 0.05, 0.0),
+        ];
+        let opts = Options::default();
+
+        for reversed in [false, true] {
+            let mut states = states.to_vec();
+            if reversed {
+                states.reverse();
+            }
+            let results = nearest_planes(&observer, &states, &opts, None);
+            let order: Vec<&str> = results.iter().map(|(_, s)| s.icao24.as_str()).collect();
+            assert_eq!(order, vec!["a00001", "3c6444", "4ca2b0", "4ca2b1"]);
+        }
+    }
+
+    #[test]
+    fn test_slant_range_warning() {
+        let states = [
+            OpenskyState {
+                baro_altitude: Some(3000.0),
+                ..synthetic_state("a", 53.3, -6.2)
+            },
+            synthetic_state("b", 53.4, -6.2),
+            OpenskyState {
+                geo_altitude: Some(9000.0),
+                ..synthetic_state("c", 53.5, -6.2)
+            },
+            synthetic_state("d", 53.6, -6.2),
+            synthetic_state("e", 53.7, -6.2),
+        ];
+        let results: Vec<(f64, &OpenskyState)> = states.iter().map(|s| (1.0, s)).collect();
+        let opts = Options {
+            slant_range: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            slant_range_warning(&opts, &results).unwrap(),
+            "slant range: 3 of 5 planes lacked altitude, using ground distance"
+        );
+        assert_eq!(slant_range_warning(&opts, &results[..1]), None);
+        assert_eq!(slant_range_warning(&Options::default(), &results), None);
+    }
+
+    #[test]
+    fn test_reference_time() {
+        let states = [
+            OpenskyState {
+                last_contact: 1595268600,
+                response_time: Some(1595268620),
+                ..synthetic_state("a", 53.3, -6.2)
+            },
+            synthetic_state("b", 53.4, -6.2),
+        ];
+        let results: Vec<(f64, &OpenskyState)> = states.iter().map(|s| (1.0, s)).collect();
+        let now = reference_time(&results);
+        assert_eq!(now, 1595268620);
+
+        // ages count back from OpenSky's snapshot rather than the local clock
+        let opts = Options {
+            tz: TimeZoneChoice::Utc,
+            ..Options::default()
+        };
+        let p = Point {
+            lat: 53.3,
+            lon: -6.2,
+        };
+        let rendered = render_result(&opts, &[Sighting::new(&opts, &p, &states[0], 1.0, now)], 1);
+        assert!(rendered.contains("\nLast contact: 2020-07-20 18:10:00 UTC (20s ago)\n"));
+        assert!(rendered.contains("\nData time: 2020-07-20 18:10:20 UTC (0s ago)\n"));
+
+        // without a snapshot time, the local clock is all there is
+        assert!(reference_time(&results[1..]) > 1595268620);
+    }
+
+    #[test]
+    fn test_busyness_line() {
+        let states = [
+            synthetic_state("a", 53.3, -6.2),
+            synthetic_state("b", 53.4, -6.2),
+            synthetic_state("c", 53.5, -6.2),
+        ];
+        let results: Vec<(f64, &OpenskyState)> = [5.0, 20.0, 80.0]
+            .iter()
+            .zip(&states)
+            .map(|(&d, s)| (d, s))
+            .collect();
+
+        let opts = Options {
+            busyness: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            busyness_line(&opts, &results),
+            "Busyness: 14/100 (2 planes within 50.0 km)"
+        );
+
+        // --radius stands in for --busyness-radius
+        let opts = Options {
+            radius: Some(10.0),
+            ..opts
+        };
+        assert_eq!(
+            busyness_line(&opts, &results),
+            "Busyness: 5/100 (1 plane within 10.0 km)"
+        );
+    }
+
+    #[test]
+    fn test_prefilter_keeps_everything_in_range() {
+        // a deterministic scatter of planes over the whole globe, denser near each observer
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let observers = [
+            (53.21, -6.18),
+            (0.0, 179.95),
+            (-33.87, 151.21),
+            (89.2, 40.0),
+            (-88.5, -120.0),
+        ];
+        let mut states = Vec::new();
+        for (i, &(lat, lon)) in observers.iter().enumerate() {
+            for j in 0..400 {
+                let spread = if j % 2 == 0 { 4.0 } else { 180.0 };
+                let plane_lat = (lat + (next() - 0.5) * spread).clamp(-90.0, 90.0);
+                let mut plane_lon = lon + (next() - 0.5) * spread * 2.0;
+                if plane_lon > 180.0 {
+                    plane_lon -= 360.0;
+                } else if plane_lon < -180.0 {
+                    plane_lon += 360.0;
+                }
+                states.push(synthetic_state(
+                    &format!("{}-{}", i, j),
+                    plane_lat,
+                    plane_lon,
+                ));
+            }
+        }
+
+        for &(lat, lon) in &observers {
+            let observer = Point { lat, lon };
+            for &(radius, units) in &[(25.0, Units::Km), (100.0, Units::Nmi), (3000.0, Units::Mi)] {
+                let opts = Options {
+                    radius: Some(radius),
+                    units,
+                    ..Options::default()
+                };
+                let exact: Vec<&str> = states
+                    .iter()
+                    .filter(|s| opts.within_radius(opts.distance(&observer, s.position().unwrap())))
+                    .map(|s| s.icao24.as_str())
+                    .collect();
+                let mut filtered: Vec<&str> = nearest_planes(&observer, &states, &opts, None)
+                    .iter()
+                    .map(|(_, s)| s.icao24.as_str())
+                    .collect();
+                let mut exact = exact;
+                exact.sort_unstable();
+                filtered.sort_unstable();
+                assert_eq!(
+                    filtered, exact,
+                    "{:?} within {} {:?}",
+                    observer, radius, units
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_watch_stop_condition() {
+        // nearest plane per poll, one poll a minute, giving up after five minutes
+        let interval = Duration::from_secs(60);
+        let timeout = Some(Duration::from_secs(300));
+        let run = |polls: &[Option<f64>]| {
+            polls
+                .iter()
+                .enumerate()
+                .map(|(i, &nearest)| {
+                    watch_step(nearest, 2.0, interval * i as u32, interval, timeout)
+                })
+                .find(|step| *step != WatchStep::Continue)
+        };
+
+        assert_eq!(
+            run(&[None, Some(8.5), Some(4.1), Some(1.9)]),
+            Some(WatchStep::Found)
+        );
+        assert_eq!(run(&[Some(2.0)]), Some(WatchStep::Found));
+        assert_eq!(run(&[Some(3.0); 5]), None);
+        assert_eq!(run(&[Some(3.0); 6]), Some(WatchStep::TimedOut));
+        // a plane turning up on the last poll before the deadline still counts
+        assert_eq!(
+            run(&[None, None, None, None, None, Some(0.4)]),
+            Some(WatchStep::Found)
+        );
+
+        // without a timeout it keeps waiting
+        assert_eq!(
+            watch_step(None, 2.0, Duration::from_secs(86_400), interval, None),
+            WatchStep::Continue
+        );
+    }
+
+    #[test]
+    fn test_compare_observers() {
+        let states = [
+            synthetic_state("4ca123", 53.42, -6.27),
+            synthetic_state("a1b2c3", 53.3, -6.2),
+        ];
+        let dublin_airport = Point {
+            lat: 53.4264,
+            lon: -6.2499,
+        };
+        let howth = Point {
+            lat: 53.3786,
+            lon: -6.0574,
+        };
+        let opts = Options {
+            track: Some("4CA123".to_string()),
+            ..Options::default()
+        };
+
+        let sightings = nearest_from_each(&[dublin_airport, howth], &states, &opts);
+        let plane = Point {
+            lat: 53.42,
+            lon: -6.27,
+        };
+        let distances: Vec<f64> = sightings
+            .iter()
+            .map(|sighting| {
+                let (distance, state) = sighting.unwrap();
+                assert_eq!(state.icao24, "4ca123");
+                distance
+            })
+            .collect();
+        assert!((distances[0] - haversine(&dublin_airport, &plane)).abs() < 1e-9);
+        assert!((distances[1] - haversine(&howth, &plane)).abs() < 1e-9);
+        assert!(distances[0] < 2.0 && distances[1] > 14.0);
+
+        // only the first observer has it in range
+        let opts = Options {
+            radius: Some(5.0),
+            ..opts
+        };
+        let sightings = nearest_from_each(&[dublin_airport, howth], &states, &opts);
+        assert!(sightings[0].is_some() && sightings[1].is_none());
+    }
+
+    #[test]
+    fn test_nearest_n_matches_full_sort() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        // a grid of planes, with some equally distant pairs mirrored across the observer
+        let mut states = Vec::new();
+        for i in 0..15 {
+            for j in 0..15 {
+                let (lat, lon) = (52.5 + i as f64 * 0.1, -7.0 + j as f64 * 0.1);
+                states.push(synthetic_state(&format!("{:02}{:02}", i, j), lat, lon));
+            }
+        }
+        states.push(synthetic_state("mirror-a", 53.31, -6.18));
+        states.push(synthetic_state("mirror-b", 53.11, -6.18));
+
+        let all = nearest_planes(&observer, &states, &Options::default(), None);
+        for n in [1, 2, 5, 40, states.len(), states.len() + 10] {
+            let opts = Options {
+                max_planes: Some(n),
+                ..Options::default()
+            };
+            let capped = nearest_planes(&observer, &states, &opts, None);
+            let expected = &all[..n.min(all.len())];
+            assert_eq!(capped.len(), expected.len());
+            for ((d1, s1), (d2, s2)) in capped.iter().zip(expected) {
+                assert_eq!((d1, &s1.icao24), (d2, &s2.icao24));
+            }
+        }
+    }
+
+    #[cfg(feature = "formats")]
+    #[test]
+    fn test_json_errors() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(json_errors(&args(&["--json"])));
+        assert!(json_errors(&args(&["--format", "json", "--lat"])));
+        assert!(json_errors(&args(&["--json", "--bogus"])));
+        assert!(!json_errors(&args(&["--format", "csv"])));
+        assert!(!json_errors(&args(&[])));
+
+        let error = run(args(&["--json", "--bogus"]).into_iter()).unwrap_err();
+        let json: serde_json::Value = serde_json::from_str(&error.to_json()).unwrap();
+        assert_eq!(json["kind"], "args");
+        assert_eq!(json["error"], "Unknown argument: --bogus");
+        assert_eq!(error.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_empty_ok() {
+        let empty = || Err(Error::NoResults);
+
+        let result = empty_ok(&Options::default(), empty());
+        assert!(matches!(result, Err(Error::NoResults)));
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+
+        let opts = Options {
+            empty_ok: true,
+            ..Options::default()
+        };
+        assert!(empty_ok(&opts, empty()).is_ok());
+        // other failures still fail
+        let network = Err(Error::Network("timed out".to_string()));
+        assert!(matches!(empty_ok(&opts, network), Err(Error::Network(_))));
+    }
+
+    #[test]
+    fn test_watch_diff_changes() {
+        let poll = |icao24: &str, distance: f64| Some((icao24.to_string(), distance));
+        // the nearest plane changes on the third tick, then the sky empties and refills
+        let polls: Vec<Nearest> = vec![
+            poll("4ca123", 12.0),
+            poll("4ca123", 9.5),
+            poll("a1b2c3", 8.0),
+            poll("a1b2c3", 7.1),
+            None,
+            None,
+            poll("a1b2c3", 6.0),
+        ];
+        let printed = |threshold: Option<f64>| -> Vec<usize> {
+            let mut last = None;
+            let mut printed = Vec::new();
+            for (tick, nearest) in polls.iter().enumerate() {
+                if watch_changed(last, nearest, threshold) {
+                    printed.push(tick + 1);
+                }
+                last = Some(nearest);
+            }
+            printed
+        };
+
+        assert_eq!(printed(None), [1, 3, 5, 7]);
+        // coming within 10 km of the same plane counts as a change too
+        assert_eq!(printed(Some(10.0)), [1, 2, 3, 5, 7]);
+    }
+
+    #[test]
+    fn test_diff_polls() {
+        let last = ["4ca123", "a1b2c3", "400abc"];
+        let current = ["a1b2c3", "3c6444", "4ca123"];
+        let (statuses, departed) = diff_polls(&last, &current);
+        assert_eq!(
+            statuses,
+            [
+                WatchStatus::Continuing,
+                WatchStatus::New,
+                WatchStatus::Continuing
+            ]
+        );
+        assert_eq!(departed, [2]);
+
+        // everything is new after an empty sky, and everything goes when it empties
+        let (statuses, departed) = diff_polls(&[], &current);
+        assert_eq!(statuses, [WatchStatus::New; 3]);
+        assert!(departed.is_empty());
+        let (statuses, departed) = diff_polls(&last, &[]);
+        assert!(statuses.is_empty());
+        assert_eq!(departed, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_watch_table_all_departed() {
+        let path = std::env::temp_dir().join(format!(
+            "nearest_airplane_departed_{}.txt",
+            std::process::id()
+        ));
+        let opts = Options {
+            watch: Some(30),
+            table: true,
+            count: Some(2),
+            output: Some(path.to_string_lossy().into_owned()),
+            ..Options::default()
+        };
+        let observer = Point {
+            lat: 53.35,
+            lon: -6.26,
+        };
+        let first = synthetic_state("4ca123", 53.42, -6.27);
+        let second = synthetic_state("a1b2c3", 53.5, -6.3);
+        let mut listed = None;
+        let departures = || {
+            fs::read_to_string(&path)
+                .unwrap()
+                .matches("departed")
+                .count()
+        };
+
+        let results = vec![(7.8, &first), (16.9, &second)];
+        write_results(&opts, &observer, None, results, Some(&mut listed)).unwrap();
+        assert_eq!(departures(), 0);
+
+        // the sky empties, but both planes are shown leaving before they're dropped
+        let result = write_results(&opts, &observer, None, Vec::new(), Some(&mut listed));
+        assert!(matches!(result, Err(Error::NoResults)));
+        assert_eq!(departures(), 2);
+        assert_eq!(listed.as_deref().map(<[_]>::len), Some(0));
+
+        // and once gone they aren't listed again
+        fs::write(&path, "").unwrap();
+        let result = write_results(&opts, &observer, None, Vec::new(), Some(&mut listed));
+        assert!(matches!(result, Err(Error::NoResults)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use std::env;
+
+// how a locale writes numbers, e.g. 2.887,3 in German for 2887.3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberLocale {
+    pub decimal: char,
+    // None for no grouping at all, as in the C locale
+    pub thousands: Option<char>,
+}
+
+const PLAIN: NumberLocale = NumberLocale {
+    decimal: '.',
+    thousands: None,
+};
+const POINT: NumberLocale = NumberLocale {
+    decimal: '.',
+    thousands: Some(','),
+};
+const COMMA: NumberLocale = NumberLocale {
+    decimal: ',',
+    thousands: Some('.'),
+};
+// a no-break space, so a number never wraps across lines
+const COMMA_SPACE: NumberLocale = NumberLocale {
+    decimal: ',',
+    thousands: Some('\u{a0}'),
+};
+const SWISS: NumberLocale = NumberLocale {
+    decimal: '.',
+    thousands: Some('\''),
+};
+
+impl NumberLocale {
+    // a POSIX locale name like de_DE.UTF-8, or "auto" for the one in LC_ALL, LC_NUMERIC
+    // or LANG; only the language and country matter
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        if name == "auto" {
+            let detected = ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|var| env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .unwrap_or_else(|| "C".to_string());
+            return NumberLocale::from_name(&detected).or(Ok(PLAIN));
+        }
+
+        let base = name.split(['.', '@']).next().unwrap_or_default();
+        let language = base.split(['_', '-']).next().unwrap_or_default();
+        match (language.to_ascii_lowercase().as_str(), base) {
+            (_, "de_CH") | (_, "it_CH") => Ok(SWISS),
+            ("c", _) | ("posix", _) => Ok(PLAIN),
+            ("en", _)
+            | ("ja", _)
+            | ("zh", _)
+            | ("ko", _)
+            | ("he", _)
+            | ("th", _)
+            | ("hi", _)
+            | ("ga", _) => Ok(POINT),
+            ("de", _)
+            | ("es", _)
+            | ("it", _)
+            | ("nl", _)
+            | ("pt", _)
+            | ("da", _)
+            | ("id", _)
+            | ("tr", _)
+            | ("el", _)
+            | ("ro", _)
+            | ("hr", _)
+            | ("sl", _)
+            | ("sr", _)
+            | ("vi", _) => Ok(COMMA),
+            ("fr", _)
+            | ("ru", _)
+            | ("pl", _)
+            | ("cs", _)
+            | ("sk", _)
+            | ("sv", _)
+            | ("fi", _)
+            | ("nb", _)
+            | ("nn", _)
+            | ("no", _)
+            | ("uk", _)
+            | ("hu", _)
+            | ("bg", _)
+            | ("et", _)
+            | ("lt", _)
+            | ("lv", _) => Ok(COMMA_SPACE),
+            _ => Err(format!("Unknown locale for --locale: {}", name)),
+        }
+    }
+
+    // rewrite a number formatted the Rust way, like "-2887.3", with these separators
+    pub(crate) fn format(&self, number: &str) -> String {
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", number),
+        };
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (digits, None),
+        };
+        if !whole.chars().all(|c| c.is_ascii_digit()) {
+            // inf, NaN and the like have no separators to swap
+            return number.to_string();
+        }
+
+        let mut out = sign.to_string();
+        for (i, c) in whole.chars().enumerate() {
+            match self.thousands {
+                Some(thousands) if i > 0 && (whole.len() - i) % 3 == 0 => out.push(thousands),
+                _ => {}
+            }
+            out.push(c);
+        }
+        if let Some(fraction) = fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_format() {
+        let german = NumberLocale::from_name("de_DE.UTF-8").unwrap();
+        assert_eq!(german.format("2887.3"), "2.887,3");
+        assert_eq!(german.format("-1234567"), "-1.234.567");
+        assert_eq!(german.format("12.50"), "12,50");
+        assert_eq!(german.format("inf"), "inf");
+
+        let cases = [
+            ("en_US.UTF-8", "2,887.3"),
+            ("C", "2887.3"),
+            ("POSIX", "2887.3"),
+            ("fr_FR", "2\u{a0}887,3"),
+            ("pt-BR", "2.887,3"),
+            ("de_CH.UTF-8", "2'887.3"),
+            ("sv_SE.ISO-8859-1@euro", "2\u{a0}887,3"),
+        ];
+        for &(name, formatted) in &cases {
+            let locale = NumberLocale::from_name(name).unwrap();
+            assert_eq!(locale.format("2887.3"), formatted, "{}", name);
+        }
+        assert!(NumberLocale::from_name("xx_XX").is_err());
+    }
+}
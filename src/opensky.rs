@@ -0,0 +1,901 @@
+use crate::coords::{parse_coord_value, Point};
+use crate::credentials::Credentials;
+use crate::error::Error;
+use crate::geo::{elevation_angle, EARTH_RADIUS_KM};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_reader, Value};
+use std::cell::Cell;
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize)]
+pub struct OpenskyResponse {
+    // the unix time of OpenSky's snapshot
+    #[serde(default)]
+    pub time: Option<u64>,
+    pub states: Vec<OpenskyState>,
+    // malformed entries of the states array left out by parse_opensky_response
+    #[serde(skip)]
+    pub skipped: usize,
+}
+
+// the response as it arrives, before each state is checked on its own; OpenSky sends
+// null rather than an empty array when no planes match
+#[derive(Deserialize)]
+struct RawResponse {
+    time: Option<u64>,
+    states: Option<Vec<Value>>,
+}
+
+// one entry of the "states" array, which OpenSky sends as a positional JSON array
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenskyState {
+    pub icao24: String,
+    pub callsign: String,
+    pub origin_country: String,
+    pub time_position: Option<usize>,
+    pub last_contact: usize,
+    pub longitude: Option<f64>,
+    pub latitude: Option<f64>,
+    pub baro_altitude: Option<f64>,
+    pub on_ground: bool,
+    pub velocity: Option<f64>,
+    pub true_track: Option<f64>,
+    pub vertical_rate: Option<f64>,
+    pub sensors: Option<Vec<usize>>,
+    pub geo_altitude: Option<f64>,
+    pub squawk: Option<String>,
+    pub spi: bool,
+    pub position_source: usize,
+    // only sent when the request asks for extended=1, as an 18th element
+    #[serde(default)]
+    pub category: Option<u8>,
+    // where the state sat in the response's "states" array, counting ones that were skipped
+    #[serde(skip)]
+    pub response_index: Option<usize>,
+    // the snapshot time of the response the state came in, which ages are measured against
+    #[serde(skip)]
+    pub response_time: Option<u64>,
+}
+
+// optional state fields that can be required with --has ("altitude" accepts either kind)
+pub(crate) const OPTIONAL_FIELDS: &[&str] = &[
+    "time_position",
+    "longitude",
+    "latitude",
+    "baro_altitude",
+    "velocity",
+    "true_track",
+    "vertical_rate",
+    "sensors",
+    "geo_altitude",
+    "squawk",
+    "category",
+    "altitude",
+];
+
+// OpenSky's aircraft categories, indexed by their number
+const CATEGORY_LABELS: &[&str] = &[
+    "no information",
+    "no ADS-B category",
+    "light",
+    "small",
+    "large",
+    "high vortex large",
+    "heavy",
+    "high performance",
+    "rotorcraft",
+    "glider",
+    "lighter-than-air",
+    "parachutist",
+    "ultralight",
+    "reserved",
+    "UAV",
+    "space vehicle",
+    "emergency vehicle",
+    "service vehicle",
+    "point obstacle",
+    "cluster obstacle",
+    "line obstacle",
+];
+
+// a category number from its label, case-insensitively, or the number itself
+pub(crate) fn category_from_name(name: &str) -> Result<u8, String> {
+    CATEGORY_LABELS
+        .iter()
+        .position(|label| label.eq_ignore_ascii_case(name))
+        .or_else(|| {
+            name.parse::<usize>()
+                .ok()
+                .filter(|&n| n < CATEGORY_LABELS.len())
+        })
+        .map(|n| n as u8)
+        .ok_or_else(|| {
+            format!(
+                "Unknown aircraft category: {} (expected one of {})",
+                name,
+                CATEGORY_LABELS.join(", ")
+            )
+        })
+}
+
+// planes at least this high above the horizon count as directly overhead
+const OVERHEAD_ELEVATION_DEG: f64 = 70.0;
+
+impl OpenskyState {
+    // barometric altitude where known, otherwise geometric
+    pub fn altitude(&self) -> Option<f64> {
+        self.baro_altitude.or(self.geo_altitude)
+    }
+
+    // geometric minus barometric altitude in metres, which reflects the local air pressure
+    pub fn altitude_delta(&self) -> Option<f64> {
+        match (self.geo_altitude, self.baro_altitude) {
+            (Some(geo), Some(baro)) => Some(geo - baro),
+            _ => None,
+        }
+    }
+
+    // a readable name for the aircraft category, where OpenSky sent one
+    pub fn category_label(&self) -> Option<&'static str> {
+        self.category
+            .and_then(|category| CATEGORY_LABELS.get(category as usize))
+            .copied()
+    }
+
+    // degrees above the horizon for an observer this far away on the ground
+    pub fn elevation(&self, distance_km: f64) -> Option<f64> {
+        self.altitude()
+            .map(|altitude| elevation_angle(distance_km, altitude))
+    }
+
+    // planes without an altitude are never considered overhead
+    pub fn is_overhead(&self, distance_km: f64) -> bool {
+        self.elevation(distance_km)
+            .is_some_and(|elevation| elevation > OVERHEAD_ELEVATION_DEG)
+    }
+
+    pub fn position(&self) -> Option<Point> {
+        match (self.latitude, self.longitude) {
+            (Some(lat), Some(lon)) => Some(Point { lat, lon }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn has_field(&self, name: &str) -> bool {
+        match name {
+            "time_position" => self.time_position.is_some(),
+            "longitude" => self.longitude.is_some(),
+            "latitude" => self.latitude.is_some(),
+            "baro_altitude" => self.baro_altitude.is_some(),
+            "velocity" => self.velocity.is_some(),
+            "true_track" => self.true_track.is_some(),
+            "vertical_rate" => self.vertical_rate.is_some(),
+            "sensors" => self.sensors.is_some(),
+            "geo_altitude" => self.geo_altitude.is_some(),
+            "squawk" => self.squawk.is_some(),
+            "category" => self.category.is_some(),
+            "altitude" => self.baro_altitude.is_some() || self.geo_altitude.is_some(),
+            _ => false,
+        }
+    }
+}
+
+// the area to request from OpenSky, in decimal degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub lamin: f64,
+    pub lomin: f64,
+    pub lamax: f64,
+    pub lomax: f64,
+}
+
+impl BoundingBox {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let values = s
+            .split(',')
+            .map(|v| parse_coord_value(v.trim()))
+            .collect::<Result<Vec<f64>, String>>()?;
+        let bbox = match values[..] {
+            [lamin, lomin, lamax, lomax] => BoundingBox {
+                lamin,
+                lomin,
+                lamax,
+                lomax,
+            },
+            _ => {
+                return Err(format!(
+                    "Expected lamin,lomin,lamax,lomax for --bbox, got {} values",
+                    values.len()
+                ))
+            }
+        };
+
+        if bbox.lamin >= bbox.lamax || bbox.lomin >= bbox.lomax {
+            return Err(format!(
+                "Bounding box minimums must be below maximums: {}",
+                s
+            ));
+        }
+        if bbox.lamin < -90.0 || bbox.lamax > 90.0 || bbox.lomin < -180.0 || bbox.lomax > 180.0 {
+            return Err(format!("Bounding box out of range: {}", s));
+        }
+
+        Ok(bbox)
+    }
+
+    // a box reaching roughly km in each direction from center, or None if it would cross
+    // the antimeridian, where a box can't wrap and everywhere has to be fetched instead
+    pub(crate) fn around(center: &Point, km: f64) -> Option<Self> {
+        let half_lat = (km / EARTH_RADIUS_KM).to_degrees().min(90.0);
+        let half_lon = (half_lat / center.lat.to_radians().cos().max(0.01)).min(180.0);
+
+        BoundingBox::within_antimeridian(center, half_lat, half_lon)
+    }
+
+    pub(crate) fn contains(&self, p: &Point) -> bool {
+        (self.lamin..=self.lamax).contains(&p.lat) && (self.lomin..=self.lomax).contains(&p.lon)
+    }
+
+    pub fn center(&self) -> Point {
+        Point {
+            lat: (self.lamin + self.lamax) / 2.0,
+            lon: (self.lomin + self.lomax) / 2.0,
+        }
+    }
+
+    // grow the box about its centre, stopping at the poles; None once it would cross the
+    // antimeridian, as with around
+    pub(crate) fn scaled(&self, factor: f64) -> Option<Self> {
+        let half_lat = (self.lamax - self.lamin) / 2.0 * factor;
+        let half_lon = (self.lomax - self.lomin) / 2.0 * factor;
+
+        BoundingBox::within_antimeridian(&self.center(), half_lat, half_lon)
+    }
+
+    fn within_antimeridian(center: &Point, half_lat: f64, half_lon: f64) -> Option<Self> {
+        let (lomin, lomax) = (center.lon - half_lon, center.lon + half_lon);
+        if lomin < -180.0 || lomax > 180.0 {
+            return None;
+        }
+
+        Some(BoundingBox {
+            lamin: (center.lat - half_lat).max(-90.0),
+            lomin,
+            lamax: (center.lat + half_lat).min(90.0),
+            lomax,
+        })
+    }
+}
+
+// anywhere plane states can be fetched from, optionally limited to a box
+pub trait PlaneSource {
+    fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error>;
+}
+
+const DEFAULT_OPENSKY_URL: &str = "https://opensky-network.org/api/states/all";
+
+pub struct OpenskyApi {
+    pub url: String,
+    // ask for the extended state vectors, which add the aircraft category
+    pub extended: bool,
+    pub credentials: Option<Credentials>,
+}
+
+impl OpenskyApi {
+    // the API endpoint can be overridden with OPENSKY_URL, e.g. to point at a local mirror,
+    // and an account given with OPENSKY_USERNAME and OPENSKY_PASSWORD
+    pub fn from_env() -> Self {
+        OpenskyApi {
+            url: env::var("OPENSKY_URL").unwrap_or_else(|_| DEFAULT_OPENSKY_URL.to_string()),
+            extended: false,
+            credentials: Credentials::from_env(),
+        }
+    }
+}
+
+impl PlaneSource for OpenskyApi {
+    fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+        let mut request = attohttpc::get(&self.url);
+        if let Some(bbox) = bbox {
+            request = request
+                .param("lamin", bbox.lamin)
+                .param("lomin", bbox.lomin)
+                .param("lamax", bbox.lamax)
+                .param("lomax", bbox.lomax);
+        }
+        if self.extended {
+            request = request.param("extended", 1);
+        }
+        if let Some(credentials) = &self.credentials {
+            request = request.header("Authorization", credentials.authorization());
+        }
+
+        let data = request
+            .send()
+            .and_then(|resp| resp.bytes())
+            .map_err(|e| Error::Network(e.to_string()))?;
+        let response = parse_opensky_response(data)?;
+        if response.skipped > 0 {
+            eprintln!("Skipped {} malformed plane states.", response.skipped);
+        }
+        Ok(response.states)
+    }
+}
+
+// tries each source in turn until one answers, e.g. OpenSky mirrors listed with --api-urls
+pub(crate) struct Failover {
+    pub(crate) sources: Vec<(String, Box<dyn PlaneSource>)>,
+}
+
+impl PlaneSource for Failover {
+    fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+        let mut last_error = None;
+
+        for (name, source) in &self.sources {
+            match source.fetch(bbox) {
+                Ok(states) => {
+                    eprintln!("Fetched plane states from {}", name);
+                    return Ok(states);
+                }
+                Err(e) => {
+                    eprintln!("{} failed: {}", name, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| Error::Network("no API URLs to try".to_string())))
+    }
+}
+
+// OpenSky's own limits on how often each client may ask for fresh states
+pub(crate) const ANONYMOUS_MIN_INTERVAL_SECS: f64 = 10.0;
+pub(crate) const ACCOUNT_MIN_INTERVAL_SECS: f64 = 5.0;
+
+// keeps real requests at least an interval apart, however many widened boxes, failed
+// mirrors or watch ticks ask for them
+pub(crate) struct MinInterval {
+    interval: Duration,
+    // when the last permitted call went ahead
+    last: Cell<Option<Instant>>,
+}
+
+impl MinInterval {
+    pub(crate) fn new(interval: Duration) -> Self {
+        MinInterval {
+            interval,
+            last: Cell::new(None),
+        }
+    }
+
+    // how long a call made at `now` has to wait, counting it as made once the wait is over
+    pub(crate) fn delay(&self, now: Instant) -> Duration {
+        let ready = match self.last.get() {
+            Some(last) => (last + self.interval).max(now),
+            None => now,
+        };
+        self.last.set(Some(ready));
+        ready - now
+    }
+}
+
+// a source whose requests wait their turn behind a MinInterval
+pub(crate) struct RateLimited {
+    pub(crate) source: Box<dyn PlaneSource>,
+    pub(crate) limiter: MinInterval,
+}
+
+impl PlaneSource for RateLimited {
+    fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+        let delay = self.limiter.delay(Instant::now());
+        if !delay.is_zero() {
+            eprintln!(
+                "Waiting {:.1}s to stay within the API rate limit.",
+                delay.as_secs_f64()
+            );
+            thread::sleep(delay);
+        }
+        self.source.fetch(bbox)
+    }
+}
+
+pub(crate) const DEFAULT_SEARCH_KM: f64 = 100.0;
+const MAX_BOX_EXPANSIONS: usize = 4;
+
+// keep doubling the box until it holds enough positioned planes, or we run out of attempts;
+// a box that can't grow without crossing the antimeridian gives way to fetching everywhere
+pub(crate) fn fetch_with_min_results(
+    source: &dyn PlaneSource,
+    bbox: Option<BoundingBox>,
+    min_results: usize,
+) -> Result<Vec<OpenskyState>, Error> {
+    let mut bbox = bbox;
+    let mut states = source.fetch(bbox.as_ref())?;
+
+    for _ in 0..MAX_BOX_EXPANSIONS {
+        let found = states.iter().filter(|s| s.position().is_some()).count();
+        // with no box there's nowhere left to look
+        let wider = match &bbox {
+            Some(bbox) => bbox.scaled(2.0),
+            None => break,
+        };
+        if found >= min_results || wider == bbox {
+            break;
+        }
+
+        eprintln!(
+            "Only {} of {} wanted planes in the search box, widening it.",
+            found, min_results
+        );
+        bbox = wider;
+        states = source.fetch(bbox.as_ref())?;
+    }
+
+    Ok(states)
+}
+
+// a state that doesn't fit the schema is counted and dropped instead of failing the batch
+pub(crate) fn parse_opensky_response(data: Vec<u8>) -> Result<OpenskyResponse, Error> {
+    let raw: RawResponse = from_reader(&data[..]).map_err(Error::Json)?;
+
+    let mut states = Vec::new();
+    let mut skipped = 0;
+    for (i, value) in raw.states.unwrap_or_default().into_iter().enumerate() {
+        match serde_json::from_value::<OpenskyState>(value) {
+            Ok(state) => states.push(OpenskyState {
+                response_index: Some(i),
+                response_time: raw.time,
+                ..state
+            }),
+            Err(_) => skipped += 1,
+        }
+    }
+
+    Ok(OpenskyResponse {
+        time: raw.time,
+        states,
+        skipped,
+    })
+}
+
+// the positions and names of the state fields OpenSky always fills in
+const REQUIRED_FIELDS: &[(usize, &str)] = &[
+    (0, "icao24"),
+    (1, "callsign"),
+    (2, "origin_country"),
+    (4, "last_contact"),
+    (8, "on_ground"),
+    (15, "spi"),
+    (16, "position_source"),
+];
+// 17 fields, or 18 with the extended category
+const STATE_LENGTHS: [usize; 2] = [17, 18];
+
+// what --validate found in a saved response
+#[derive(Debug, PartialEq)]
+pub(crate) struct Validation {
+    pub(crate) parsed: usize,
+    pub(crate) skipped: usize,
+    pub(crate) surprises: Vec<String>,
+}
+
+// parse a response the way a real fetch would, then look harder at anything out of line
+// with the schema, whether or not it stopped the state from parsing
+pub(crate) fn validate_response(data: Vec<u8>) -> Result<Validation, Error> {
+    let raw: RawResponse = from_reader(&data[..]).map_err(Error::Json)?;
+    let mut surprises = Vec::new();
+    if raw.time.is_none() {
+        surprises.push("response: no time".to_string());
+    }
+
+    for (i, value) in raw.states.iter().flatten().enumerate() {
+        let fields = match value.as_array() {
+            Some(fields) => fields,
+            None => {
+                surprises.push(format!("state {}: not an array", i));
+                continue;
+            }
+        };
+        if !STATE_LENGTHS.contains(&fields.len()) {
+            surprises.push(format!(
+                "state {}: {} fields, expected 17 or 18",
+                i,
+                fields.len()
+            ));
+        }
+        for &(position, name) in REQUIRED_FIELDS {
+            if fields.get(position).is_some_and(Value::is_null) {
+                surprises.push(format!("state {}: {} is null", i, name));
+            }
+        }
+        if let Err(e) = serde_json::from_value::<OpenskyState>(value.clone()) {
+            surprises.push(format!("state {}: {}", i, e));
+        }
+    }
+
+    let response = parse_opensky_response(data)?;
+    Ok(Validation {
+        parsed: response.states.len(),
+        skipped: response.skipped,
+        surprises,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::haversine;
+    use crate::test_util::{read_file_bytes, synthetic_state};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_parse_opensky_response() {
+        let data = read_file_bytes("test/opensky_states_all.json");
+        let states = parse_opensky_response(data).unwrap().states;
+        assert_eq!(states.len(), 4969);
+        assert_eq!(states[0].squawk, Some("1571".to_string()));
+    }
+
+    #[test]
+    fn test_parse_extended_state() {
+        let data = br#"{"time": 1595268620, "states": [
+            ["a808c4", "PDT4901 ", "United States", 1595268619, 1595268619, -79.367, 43.6283,
+             1722.12, false, 126.23, null, -8.45, null, 1752.6, "1571", false, 0, 6],
+            ["a2e5ec", "SKW3508 ", "United States", 1595268619, 1595268619, -117.1141, 34.1757,
+             5913.12, false, 192.62, 251.95, -10.08, null, 6233.16, "4741", false, 0, null],
+            ["4ca123", "EIN123  ", "Ireland", 1595268619, 1595268619, -6.27, 53.42,
+             3048.0, false, 120.5, 270.0, 0.0, null, 3100.0, null, false, 0]
+        ]}"#;
+        let response = parse_opensky_response(data.to_vec()).unwrap();
+        assert_eq!(response.skipped, 0);
+        assert_eq!(response.time, Some(1595268620));
+        assert!(response
+            .states
+            .iter()
+            .all(|s| s.response_time == Some(1595268620)));
+        let states = response.states;
+        assert_eq!(states[0].category, Some(6));
+        assert_eq!(states[0].category_label(), Some("heavy"));
+        assert_eq!(states[1].category, None);
+        assert_eq!(states[2].category_label(), None);
+
+        assert_eq!(category_from_name("Rotorcraft"), Ok(8));
+        assert_eq!(category_from_name("uav"), Ok(14));
+        assert_eq!(category_from_name("4"), Ok(4));
+        assert!(category_from_name("21").is_err());
+        assert!(category_from_name("blimp").is_err());
+    }
+
+    #[test]
+    fn test_skip_malformed_states() {
+        let data = br#"{"time": 1595268620, "states": [
+            ["a808c4", "PDT4901 ", "United States", 1595268619, 1595268619, -79.367, 43.6283,
+             1722.12, false, 126.23, null, -8.45, null, 1752.6, "1571", false, 0],
+            ["a2e5ec", "SKW3508 ", "United States", "yesterday", 1595268619, -117.1141, 34.1757,
+             5913.12, false, 192.62, 251.95, -10.08, null, 6233.16, "4741", false, 0],
+            "not a state",
+            ["4ca123", "EIN123  ", "Ireland", 1595268619, 1595268619, -6.27, 53.42,
+             3048.0, false, 120.5, 270.0, 0.0, null, 3100.0, null, false, 0]
+        ]}"#;
+        let response = parse_opensky_response(data.to_vec()).unwrap();
+        let icao24s: Vec<&str> = response.states.iter().map(|s| s.icao24.as_str()).collect();
+        assert_eq!(icao24s, vec!["a808c4", "4ca123"]);
+        assert_eq!(response.skipped, 2);
+        // the index still points into the raw array, skipped entries included
+        let indices: Vec<_> = response.states.iter().map(|s| s.response_index).collect();
+        assert_eq!(indices, vec![Some(0), Some(3)]);
+
+        // no planes at all comes back as null
+        let response = parse_opensky_response(br#"{"time": 1, "states": null}"#.to_vec()).unwrap();
+        assert!(response.states.is_empty());
+        assert_eq!(response.time, Some(1));
+        assert_eq!(response.skipped, 0);
+
+        // a response that isn't JSON at all still fails
+        assert!(parse_opensky_response(b"<html>".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_validate_response() {
+        let data = read_file_bytes("test/opensky_states_all.json");
+        let validation = validate_response(data).unwrap();
+        assert_eq!(
+            validation,
+            Validation {
+                parsed: 4969,
+                skipped: 0,
+                surprises: vec![],
+            }
+        );
+
+        let data = br#"{"states": [
+            ["a808c4", "PDT4901 ", "United States", 1595268619, 1595268619, -79.367, 43.6283,
+             1722.12, false, 126.23, null, -8.45, null, 1752.6, "1571", false, 0],
+            ["a2e5ec", null, "United States", 1595268619, 1595268619, -117.1141, 34.1757,
+             5913.12, false, 192.62, 251.95, -10.08, null, 6233.16, "4741", false, 0],
+            ["4ca123", "EIN123  ", "Ireland", 1595268619, 1595268619, -6.27, 53.42,
+             3048.0, false, 120.5, 270.0, 0.0, null, 3100.0, null, false, 0, 6, "spare"],
+            "not a state"
+        ]}"#;
+        let validation = validate_response(data.to_vec()).unwrap();
+        assert_eq!((validation.parsed, validation.skipped), (1, 3));
+        assert_eq!(validation.surprises[0], "response: no time");
+        assert_eq!(validation.surprises[1], "state 1: callsign is null");
+        assert!(validation.surprises[2].starts_with("state 1: invalid type: null"));
+        assert_eq!(
+            validation.surprises[3],
+            "state 2: 19 fields, expected 17 or 18"
+        );
+        assert!(validation.surprises[4].starts_with("state 2: "));
+        assert_eq!(validation.surprises[5], "state 3: not an array");
+        assert_eq!(validation.surprises.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_null_true_track() {
+        let data = br#"{"time": 1595268620, "states": [
+            ["a808c4", "PDT4901 ", "United States", 1595268619, 1595268619, -79.367, 43.6283,
+             1722.12, false, 126.23, null, -8.45, null, 1752.6, "1571", false, 0],
+            ["a2e5ec", "SKW3508 ", "United States", 1595268619, 1595268619, -117.1141, 34.1757,
+             5913.12, false, 192.62, 251.95, -10.08, null, 6233.16, "4741", false, 0]
+        ]}"#;
+        let states = parse_opensky_response(data.to_vec()).unwrap().states;
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].true_track, None);
+        assert_eq!(states[1].true_track, Some(251.95));
+    }
+
+    #[test]
+    fn test_parse_bbox() {
+        let bbox = BoundingBox::parse("51.4, -10.5, 55.4, -5.4").unwrap();
+        assert_eq!(
+            bbox,
+            BoundingBox {
+                lamin: 51.4,
+                lomin: -10.5,
+                lamax: 55.4,
+                lomax: -5.4
+            }
+        );
+        let center = bbox.center();
+        assert!((center.lat - 53.4).abs() < 1e-9 && (center.lon + 7.95).abs() < 1e-9);
+
+        // inverted latitude, inverted longitude, wrong arity, out of range
+        assert!(BoundingBox::parse("55.4,-10.5,51.4,-5.4").is_err());
+        assert!(BoundingBox::parse("51.4,-5.4,55.4,-10.5").is_err());
+        assert!(BoundingBox::parse("51.4,-10.5,55.4").is_err());
+        assert!(BoundingBox::parse("-95,-10.5,55.4,-5.4").is_err());
+    }
+
+    // returns one positioned plane per whole degree of box height, recording each request;
+    // no box is the whole map
+    struct BoxSizedSource {
+        requests: RefCell<Vec<BoundingBox>>,
+    }
+
+    impl PlaneSource for BoxSizedSource {
+        fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+            let bbox = bbox.copied().unwrap_or(BoundingBox {
+                lamin: -90.0,
+                lomin: -180.0,
+                lamax: 90.0,
+                lomax: 180.0,
+            });
+            self.requests.borrow_mut().push(bbox);
+            let count = (bbox.lamax - bbox.lamin) as usize;
+            Ok((0..count)
+                .map(|i| synthetic_state(&i.to_string(), bbox.lamin, bbox.lomin))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_altitude_delta() {
+        let state = |geo_altitude, baro_altitude| OpenskyState {
+            geo_altitude,
+            baro_altitude,
+            ..synthetic_state("4ca123", 53.0, -6.0)
+        };
+        assert_eq!(
+            state(Some(10210.8), Some(10058.4)).altitude_delta(),
+            Some(10210.8 - 10058.4)
+        );
+        assert_eq!(
+            state(Some(990.0), Some(1005.0)).altitude_delta(),
+            Some(-15.0)
+        );
+        assert_eq!(state(None, Some(1005.0)).altitude_delta(), None);
+        assert_eq!(state(Some(990.0), None).altitude_delta(), None);
+        assert_eq!(state(None, None).altitude_delta(), None);
+    }
+
+    #[test]
+    fn test_overhead() {
+        // 10 km up and 2 km away is about 79° above the horizon
+        let high = OpenskyState {
+            baro_altitude: Some(10000.0),
+            ..synthetic_state("high", 53.0, -6.0)
+        };
+        assert!(high.is_overhead(2.0));
+        // the same plane 20 km away is only about 27° up
+        assert!(!high.is_overhead(20.0));
+
+        let low = OpenskyState {
+            geo_altitude: Some(300.0),
+            ..synthetic_state("low", 53.0, -6.0)
+        };
+        assert!(!low.is_overhead(15.0));
+        assert!(low.is_overhead(0.05));
+
+        let unknown = synthetic_state("unknown", 53.0, -6.0);
+        assert!(!unknown.is_overhead(0.0));
+    }
+
+    // always fails, or always returns its one plane, counting the calls either way
+    struct FakeMirror {
+        plane: Option<&'static str>,
+        calls: Rc<RefCell<usize>>,
+    }
+
+    impl PlaneSource for FakeMirror {
+        fn fetch(&self, _bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+            *self.calls.borrow_mut() += 1;
+            match self.plane {
+                Some(icao24) => Ok(vec![synthetic_state(icao24, 53.0, -6.0)]),
+                None => Err(Error::Network("503 Service Unavailable".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_failover() {
+        let calls: Vec<Rc<RefCell<usize>>> = (0..3).map(|_| Rc::new(RefCell::new(0))).collect();
+        let mirror = |i: usize, plane| -> (String, Box<dyn PlaneSource>) {
+            let source = FakeMirror {
+                plane,
+                calls: Rc::clone(&calls[i]),
+            };
+            (format!("mirror{}", i), Box::new(source))
+        };
+        let failover = Failover {
+            sources: vec![
+                mirror(0, None),
+                mirror(1, Some("second")),
+                mirror(2, Some("third")),
+            ],
+        };
+
+        let states = failover.fetch(None).unwrap();
+        assert_eq!(states[0].icao24, "second");
+        let counts: Vec<usize> = calls.iter().map(|c| *c.borrow()).collect();
+        assert_eq!(counts, vec![1, 1, 0]);
+
+        let failover = Failover {
+            sources: vec![mirror(0, None), mirror(0, None)],
+        };
+        assert!(matches!(failover.fetch(None), Err(Error::Network(_))));
+        assert!(Failover { sources: vec![] }.fetch(None).is_err());
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let limiter = MinInterval::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // a call asked for too soon waits until 10s after the last one; a late one goes at once
+        let permitted: Vec<Instant> = [0, 0, 3, 45, 46]
+            .iter()
+            .map(|&secs| at(secs) + limiter.delay(at(secs)))
+            .collect();
+        assert_eq!(permitted, vec![at(0), at(10), at(20), at(45), at(55)]);
+        for pair in permitted.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_secs(10));
+        }
+
+        // a zero interval never waits
+        let limiter = MinInterval::new(Duration::ZERO);
+        assert!([0, 0, 1]
+            .iter()
+            .all(|&secs| limiter.delay(at(secs)).is_zero()));
+    }
+
+    #[test]
+    fn test_box_expansion() {
+        let source = BoxSizedSource {
+            requests: RefCell::new(Vec::new()),
+        };
+        let bbox = BoundingBox::parse("52,-8,54,-4").unwrap();
+
+        // 2, 4, 8 planes: the third box is enough
+        let states = fetch_with_min_results(&source, Some(bbox), 5).unwrap();
+        assert_eq!(states.len(), 8);
+        let heights: Vec<f64> = source
+            .requests
+            .borrow()
+            .iter()
+            .map(|b| b.lamax - b.lamin)
+            .collect();
+        assert_eq!(heights, vec![2.0, 4.0, 8.0]);
+        assert_eq!(source.requests.borrow()[2].center(), bbox.center());
+
+        // already enough in the first box
+        source.requests.borrow_mut().clear();
+        fetch_with_min_results(&source, Some(bbox), 2).unwrap();
+        assert_eq!(source.requests.borrow().len(), 1);
+
+        // gives up after a few doublings and returns what it has
+        source.requests.borrow_mut().clear();
+        let states = fetch_with_min_results(&source, Some(bbox), 1000).unwrap();
+        assert_eq!(source.requests.borrow().len(), 1 + MAX_BOX_EXPANSIONS);
+        assert_eq!(states.len(), 32);
+    }
+
+    #[test]
+    fn test_box_around_observer() {
+        let p = Point {
+            lat: 60.0,
+            lon: 10.0,
+        };
+        let bbox = BoundingBox::around(&p, 100.0).unwrap();
+        assert!(
+            (haversine(
+                &p,
+                &Point {
+                    lat: bbox.lamax,
+                    lon: 10.0
+                }
+            ) - 100.0)
+                .abs()
+                < 1e-6
+        );
+        // longitude degrees are half as long at 60°N
+        assert!(((bbox.lomax - bbox.lomin) / (bbox.lamax - bbox.lamin) - 2.0).abs() < 1e-9);
+
+        // expanding clamps at the poles
+        let p = Point {
+            lat: 60.0,
+            lon: 0.0,
+        };
+        let bbox = BoundingBox::around(&p, 3000.0)
+            .unwrap()
+            .scaled(2.0)
+            .unwrap();
+        assert_eq!(bbox.lamax, 90.0);
+    }
+
+    #[test]
+    fn test_box_across_antimeridian() {
+        // 100 km east of 179.5°E is past 180°, where longitudes go negative
+        let fiji = Point {
+            lat: -17.0,
+            lon: 179.5,
+        };
+        assert_eq!(BoundingBox::around(&fiji, 100.0), None);
+        let bbox = BoundingBox::around(&fiji, 10.0).unwrap();
+        assert!(bbox.lomax < 180.0);
+
+        // widening past it fetches everywhere instead, rather than stopping at 180°
+        let source = BoxSizedSource {
+            requests: RefCell::new(Vec::new()),
+        };
+        let bbox = BoundingBox::parse("52,170,54,178").unwrap();
+        let states = fetch_with_min_results(&source, Some(bbox), 5).unwrap();
+        assert_eq!(states.len(), 180);
+        let widths: Vec<f64> = source
+            .requests
+            .borrow()
+            .iter()
+            .map(|b| b.lomax - b.lomin)
+            .collect();
+        assert_eq!(widths, vec![8.0, 360.0]);
+
+        // with no box to start with, there's nothing to widen
+        source.requests.borrow_mut().clear();
+        fetch_with_min_results(&source, None, 1000).unwrap();
+        assert_eq!(source.requests.borrow().len(), 1);
+    }
+}
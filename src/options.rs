@@ -0,0 +1,1583 @@
+use crate::coords::{CoordFormat, Point};
+use crate::credentials::Credentials;
+use crate::error::Error;
+use crate::fence::Fence;
+use crate::geo::{haversine_with_radius, Prefilter, EARTH_RADIUS_KM};
+use crate::guess::GuessWeights;
+use crate::locale::NumberLocale;
+use crate::opensky::{category_from_name, BoundingBox, OpenskyState, OPTIONAL_FIELDS};
+use crate::pluscode::decode_plus_code;
+use crate::timestamps::TimeZoneChoice;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The command line help, printed alongside argument errors.
+pub const USAGE: &str = "\
+Usage: nearest_airplane [OPTIONS] < coords.txt
+
+Options:
+    --min-speed M/S     only consider planes moving at least this fast
+    --max-contact-age SECS
+                        only consider planes heard from at all in the last SECS
+                        seconds before OpenSky's snapshot
+    --descending        only consider planes sinking faster than --climb-threshold
+    --climbing          only consider planes rising faster than --climb-threshold;
+                        with --descending, either will do
+    --climb-threshold M/S
+                        vertical rate dead-band for --descending and --climbing
+                        (default 1.0)
+    --has FIELD,...     only consider planes reporting all of these optional fields
+    --lat DEG --lon DEG observer position in signed decimal degrees, instead of stdin
+    --pluscode CODE     observer position as a full plus code, e.g. 9C5M8QJ8+2V
+    --input-format FORMAT
+                        notation of the coordinates on stdin: auto (default), decimal,
+                        hemisphere, dms or nmea
+    --bbox LAMIN,LOMIN,LAMAX,LOMAX
+                        only query planes inside this box; its centre is the
+                        observer unless --lat/--lon are given
+    --slant-range       measure straight-line distance to the plane, climbing included,
+                        instead of distance along the ground; planes without an
+                        altitude fall back to ground distance
+    --earth-radius KM   sphere radius for haversine distances (default 6372.8,
+                        the mean radius; WGS-84 equatorial is 6378.137)
+    --min-results N     if the search box holds fewer than N planes, retry with a
+                        box twice the size, up to 4 times; without --bbox the
+                        first box reaches 100 km around the observer
+    --units UNITS       distance units: km (default), mi or nmi; altitudes are shown
+                        in metres with km and in feet otherwise
+    --speed-units UNITS speed units: ms, kmh, mph or knots (default kmh, mph or
+                        knots to match --units)
+    --overhead          only consider planes more than 70° above the horizon
+    --radius DISTANCE   only consider planes within this distance, in --units
+    --any               print true or false depending on whether any plane is within
+                        --radius, exiting with 0 or 1 respectively
+    --empty-ok          exit with 0 rather than 3 when no plane matches, still saying so
+    --fence FILE        only consider planes inside the polygon listed in FILE, one
+                        lon,lat vertex per line
+    --category NAME,... only consider planes of these aircraft categories, such as
+                        light, heavy or rotorcraft (asks OpenSky for extended data)
+    --country NAME,...  only consider planes registered in one of these countries
+    --exclude-country NAME,...
+                        ignore planes registered in any of these countries
+    --track ICAO24      only consider the plane with this transponder address
+    --compare LAT,LON   with --track, show that plane's distance and bearing from
+                        both the observer and this second point
+    --validate FILE     check a saved OpenSky response against the expected schema,
+                        report how many states parse and anything unexpected, and exit
+    --nearest-airport FILE
+                        skip the planes and report which place in FILE is nearest;
+                        each line of FILE is name,lat,lon
+    --histogram         instead of the nearest planes, print a bar chart counting the
+                        matching planes in each band of distance
+    --bin-width DISTANCE
+                        width of each --histogram band, in --units (default 10)
+    --radar             instead of the nearest planes, draw a map of the matching
+                        planes around the observer, north up, marked by altitude band
+    --count-by-altitude-band
+                        instead of the nearest planes, count the matching planes on
+                        the ground, in each altitude band and with no altitude
+    --altitude-bands LOW,HIGH
+                        altitudes splitting low, medium and high for --radar and
+                        --count-by-altitude-band, in metres or feet to suit --units
+                        (default 3000,9000 or 10000,30000)
+    --count N           print the nearest N planes instead of just the nearest
+    --table             print the planes as a table of callsign, distance, bearing,
+                        altitude, speed and country, with borders on a terminal;
+                        with --watch, planes new since the last poll are marked, and
+                        those that have gone are listed once more before being dropped
+    --best-guess        print the plane you're most likely looking at instead of the
+                        nearest, favouring close, high and recently seen planes
+    --guess-weights DISTANCE,ELEVATION,RECENCY
+                        how much each counts for --best-guess (default 1,2,0.5)
+    --verbose           explain the --best-guess scores on stderr
+    --busyness          also print a 0-100 score for how busy the sky is on stderr,
+                        counting each plane within the radius by how close it is
+    --busyness-radius R how far out --busyness looks (default --radius, or 50 km)
+    --busyness-power P  how sharply a plane's weight falls with distance: 0 counts
+                        every plane the same, 1 (default) falls off linearly
+    --max-planes N      keep only the nearest N planes while searching, to bound
+                        memory on small devices; --count and --histogram see no more
+    --sort-by KEY       order the printed planes by distance (default), altitude,
+                        speed or callsign; planes missing the value go last
+    --order ORDER       asc (default) or desc
+    --waypoints N       also print N points along the great-circle path to the plane
+    --show-alt-delta    also show geo_altitude minus baro_altitude when both are known
+    --contrails         mark planes high enough to be likely leaving contrails
+    --contrail-altitude ALT
+                        where --contrails starts, in metres or feet to suit --units
+                        (default 8000 m)
+    --mid-bearing       also show the bearing at the halfway point of the great-circle
+                        path, for pointing at distant planes
+    --raw-index         also show where the plane sat in OpenSky's states array
+    --arrow SOURCE      also show an arrow pointing along the bearing to the plane
+                        (bearing) or along the plane's own course (track)
+    --no-unicode        draw arrows with ASCII characters
+    --my-heading DEG    also give the plane's position as a clock position relative
+                        to your own heading, with 12 o'clock dead ahead
+    --distance-precision N
+                        decimal places for distances in human and CSV results, and
+                        CSV altitudes and speeds (default 1); JSON always has full
+                        precision
+    --distance-sig-figs N
+                        round distances to N significant figures instead
+    --no-callsign-label TEXT
+                        what to print for a plane without a callsign (default
+                        \"(no callsign)\"); JSON and CSV keep the empty callsign
+    --callsign-fallback-icao24
+                        print the icao24 instead for a plane without a callsign
+    --coord-precision N decimal places for positions in the human result (default 4)
+    --maps-url          also print Google Maps links to the plane and for directions
+                        from the observer to it
+    --osm-url           also print an OpenStreetMap link to the plane
+    --locale NAME       separators for numbers in the human result and --table, e.g.
+                        de_DE for 2.887,3, or auto to follow LC_NUMERIC; positions,
+                        JSON and CSV always use a '.'
+    --tz ZONE           show timestamps in local time (the default when built with
+                        the chrono feature), utc (otherwise) or a +HH:MM offset
+    --format FORMAT     result format: human (default), json, csv or metrics (the
+                        Prometheus text format); all but human need the formats
+                        feature, which is on by default
+    --json              shorthand for --format json; errors are then written to
+                        stdout as an object with error and kind too
+    --metrics           shorthand for --format metrics
+    --credentials-file PATH
+                        OpenSky account to use, as a user:pass line or username =
+                        and password = lines; keep it private with chmod 600
+    --api-urls URL,...  states endpoints to try in order until one answers, before
+                        OPENSKY_URL or the public OpenSky API
+    --sbs HOST[:PORT]   read planes from a local receiver's SBS (BaseStation) feed, as
+                        served by dump1090 on port 30003, instead of OpenSky
+    --sbs-window SECS   how long to listen to the --sbs feed each time (default 5)
+    --min-interval SECS wait at least SECS between requests to the same endpoint, even
+                        when widening the box or watching (default 10, or 5 with an
+                        account; 0 turns the limit off)
+    --watch SECS        keep reporting, fetching again every SECS seconds
+    --watch-diff        with --watch, only report when a different plane is nearest
+    --watch-diff-threshold DIST
+                        with --watch-diff, also report when the nearest plane's
+                        distance crosses DIST units either way
+    --watch-until-radius DIST
+                        with --watch, report nothing until a plane is within DIST
+                        units, then report it and exit
+    --watch-timeout SECS
+                        with --watch-until-radius, give up and exit 9 if no plane
+                        has come that close after SECS seconds
+    --output PATH       write the result to PATH instead of stdout; each result
+                        replaces the last in one step, so readers never see half of
+                        one, and a FIFO at PATH is written to rather than replaced
+    --append            append to the --output file instead of replacing it
+    --stdin-json        read a JSON object from stdin instead of coordinates, holding
+                        lat and lon plus any of the options above by name, e.g.
+                        {\"lat\": 53.2, \"lon\": -6.1, \"units\": \"nmi\", \"count\": 3}
+
+Environment:
+    OPENSKY_URL         states endpoint to query instead of the public OpenSky API
+    OPENSKY_USERNAME, OPENSKY_PASSWORD
+                        OpenSky account to use when there's no --credentials-file
+
+Exit codes:
+    0                   success
+    1                   false from --any
+    2                   unusable arguments
+    3                   no plane matched the filters
+    4                   the OpenSky request failed
+    5                   OpenSky's response wasn't the expected JSON
+    6                   a config or reference file, or the --stdin-json request, was
+                        unusable
+    7                   the observer coordinates couldn't be parsed
+    8                   the --track plane isn't visible
+    9                   --watch-timeout ran out
+    10                  the result couldn't be written";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub input_format: Option<CoordFormat>,
+    pub bbox: Option<BoundingBox>,
+    pub pluscode: Option<Point>,
+    pub min_results: Option<usize>,
+    pub min_speed: Option<f64>,
+    pub max_contact_age: Option<u64>,
+    pub descending: bool,
+    pub climbing: bool,
+    pub climb_threshold: Option<f64>,
+    pub radius: Option<f64>,
+    pub overhead: bool,
+    pub any: bool,
+    pub empty_ok: bool,
+    pub required_fields: Vec<String>,
+    pub fence: Option<Fence>,
+    pub categories: Vec<u8>,
+    pub countries: Vec<String>,
+    pub excluded_countries: Vec<String>,
+    pub track: Option<String>,
+    pub compare: Option<Point>,
+    pub nearest_airport: Option<String>,
+    pub validate: Option<String>,
+    pub histogram: bool,
+    pub bin_width: Option<f64>,
+    pub radar: bool,
+    pub altitude_bands: Option<[f64; 2]>,
+    pub count_by_altitude_band: bool,
+    pub count: Option<usize>,
+    pub best_guess: bool,
+    pub guess_weights: GuessWeights,
+    pub busyness: bool,
+    pub busyness_radius: Option<f64>,
+    pub busyness_power: Option<f64>,
+    pub verbose: bool,
+    pub max_planes: Option<usize>,
+    pub sort_by: SortKey,
+    pub order: SortOrder,
+    pub waypoints: Option<usize>,
+    pub earth_radius: Option<f64>,
+    pub slant_range: bool,
+    pub units: Units,
+    pub speed_units: Option<SpeedUnits>,
+    pub show_alt_delta: bool,
+    pub raw_index: bool,
+    pub mid_bearing: bool,
+    pub contrails: bool,
+    pub contrail_altitude: Option<f64>,
+    pub arrow: Option<ArrowSource>,
+    pub no_unicode: bool,
+    pub my_heading: Option<f64>,
+    pub distance_precision: Option<usize>,
+    pub distance_sig_figs: Option<usize>,
+    pub coord_precision: Option<usize>,
+    pub maps_url: bool,
+    pub table: bool,
+    pub no_callsign_label: Option<String>,
+    pub callsign_fallback_icao24: bool,
+    pub osm_url: bool,
+    pub locale: Option<NumberLocale>,
+    pub tz: TimeZoneChoice,
+    pub format: OutputFormat,
+    pub credentials: Option<Credentials>,
+    pub api_urls: Vec<String>,
+    pub min_interval: Option<f64>,
+    pub sbs: Option<String>,
+    pub sbs_window: Option<u64>,
+    pub watch: Option<u64>,
+    pub watch_diff: bool,
+    pub watch_diff_threshold: Option<f64>,
+    pub watch_until_radius: Option<f64>,
+    pub watch_timeout: Option<u64>,
+    pub output: Option<String>,
+    pub append: bool,
+    pub stdin_json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Units {
+    #[default]
+    Km,
+    Mi,
+    Nmi,
+}
+
+// vertical rates within this many m/s of level count as neither climbing nor descending
+const DEFAULT_CLIMB_THRESHOLD: f64 = 1.0;
+// the cold, thin air up here is where planes usually leave trails
+const DEFAULT_CONTRAIL_ALTITUDE_M: f64 = 8000.0;
+
+const KM_PER_MI: f64 = 1.609344;
+const KM_PER_NMI: f64 = 1.852;
+const M_PER_FT: f64 = 0.3048;
+
+impl Units {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "km" => Ok(Units::Km),
+            "mi" => Ok(Units::Mi),
+            "nmi" => Ok(Units::Nmi),
+            _ => Err(format!("Unknown units: {}", name)),
+        }
+    }
+
+    pub fn distance_label(self) -> &'static str {
+        match self {
+            Units::Km => "km",
+            Units::Mi => "mi",
+            Units::Nmi => "nmi",
+        }
+    }
+
+    pub fn distance_to_km(self, distance: f64) -> f64 {
+        distance / self.distance_from_km(1.0)
+    }
+
+    pub fn distance_from_km(self, km: f64) -> f64 {
+        match self {
+            Units::Km => km,
+            Units::Mi => km / KM_PER_MI,
+            Units::Nmi => km / KM_PER_NMI,
+        }
+    }
+
+    // aviation convention: feet go with miles and nautical miles
+    pub fn altitude_label(self) -> &'static str {
+        match self {
+            Units::Km => "m",
+            Units::Mi | Units::Nmi => "ft",
+        }
+    }
+
+    // where --altitude-bands splits low, medium and high planes unless told otherwise
+    pub fn default_altitude_bands(self) -> [f64; 2] {
+        match self {
+            Units::Km => [3000.0, 9000.0],
+            Units::Mi | Units::Nmi => [10000.0, 30000.0],
+        }
+    }
+
+    pub fn altitude_to_m(self, altitude: f64) -> f64 {
+        altitude / self.altitude_from_m(1.0)
+    }
+
+    pub fn altitude_from_m(self, m: f64) -> f64 {
+        match self {
+            Units::Km => m,
+            Units::Mi | Units::Nmi => m / M_PER_FT,
+        }
+    }
+
+    // the speed units that go with these distance units unless --speed-units says otherwise
+    pub fn default_speed_units(self) -> SpeedUnits {
+        match self {
+            Units::Km => SpeedUnits::Kmh,
+            Units::Mi => SpeedUnits::Mph,
+            Units::Nmi => SpeedUnits::Knots,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedUnits {
+    Ms,
+    Kmh,
+    Mph,
+    Knots,
+}
+
+impl SpeedUnits {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "ms" => Ok(SpeedUnits::Ms),
+            "kmh" => Ok(SpeedUnits::Kmh),
+            "mph" => Ok(SpeedUnits::Mph),
+            "knots" => Ok(SpeedUnits::Knots),
+            _ => Err(format!("Unknown speed units: {}", name)),
+        }
+    }
+
+    // the --speed-units name, which also suits CSV headers
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeedUnits::Ms => "ms",
+            SpeedUnits::Kmh => "kmh",
+            SpeedUnits::Mph => "mph",
+            SpeedUnits::Knots => "knots",
+        }
+    }
+
+    pub fn speed_label(self) -> &'static str {
+        match self {
+            SpeedUnits::Ms => "m/s",
+            SpeedUnits::Kmh => "km/h",
+            SpeedUnits::Mph => "mph",
+            SpeedUnits::Knots => "kn",
+        }
+    }
+
+    // OpenSky reports velocity in metres per second
+    pub fn speed_from_ms(self, ms: f64) -> f64 {
+        let km_per_hour = ms * 3.6;
+        match self {
+            SpeedUnits::Ms => ms,
+            SpeedUnits::Kmh => km_per_hour,
+            SpeedUnits::Mph => km_per_hour / KM_PER_MI,
+            SpeedUnits::Knots => km_per_hour / KM_PER_NMI,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    #[cfg(feature = "formats")]
+    Json,
+    #[cfg(feature = "formats")]
+    Csv,
+    #[cfg(feature = "formats")]
+    Metrics,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "human" => Ok(OutputFormat::Human),
+            #[cfg(feature = "formats")]
+            "json" => Ok(OutputFormat::Json),
+            #[cfg(feature = "formats")]
+            "csv" => Ok(OutputFormat::Csv),
+            #[cfg(feature = "formats")]
+            "metrics" => Ok(OutputFormat::Metrics),
+            #[cfg(not(feature = "formats"))]
+            "json" | "csv" | "metrics" => Err(format!(
+                "{} output isn't available in this build (enable the formats feature)",
+                name
+            )),
+            _ => Err(format!("Unknown output format: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortKey {
+    #[default]
+    Distance,
+    Altitude,
+    Speed,
+    Callsign,
+}
+
+impl SortKey {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "distance" => Ok(SortKey::Distance),
+            "altitude" => Ok(SortKey::Altitude),
+            "speed" => Ok(SortKey::Speed),
+            "callsign" => Ok(SortKey::Callsign),
+            _ => Err(format!("Unknown sort key: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            _ => Err(format!("Unknown sort order: {}", name)),
+        }
+    }
+}
+
+// which direction --arrow points in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrowSource {
+    Bearing,
+    Track,
+}
+
+impl ArrowSource {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "bearing" => Ok(ArrowSource::Bearing),
+            "track" => Ok(ArrowSource::Track),
+            _ => Err(format!("Unknown arrow source: {}", name)),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Self, Error> {
+        Options::parse_args(args).map_err(Error::Args)
+    }
+
+    fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut opts = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--lat" => opts.lat = Some(parse_number(&arg, args.next())?),
+                "--lon" => opts.lon = Some(parse_number(&arg, args.next())?),
+                "--input-format" => {
+                    let name = args.next().ok_or("Missing value for --input-format")?;
+                    opts.input_format = CoordFormat::from_name(&name)?;
+                }
+                "--pluscode" => {
+                    let code = args.next().ok_or("Missing value for --pluscode")?;
+                    opts.pluscode = Some(decode_plus_code(&code)?);
+                }
+                "--bbox" => {
+                    let value = args.next().ok_or("Missing value for --bbox")?;
+                    opts.bbox = Some(BoundingBox::parse(&value)?);
+                }
+                "--min-results" => opts.min_results = Some(parse_count(&arg, args.next())?),
+                "--min-speed" => opts.min_speed = Some(parse_number(&arg, args.next())?),
+                "--max-contact-age" => {
+                    opts.max_contact_age = Some(parse_count(&arg, args.next())? as u64)
+                }
+                "--descending" => opts.descending = true,
+                "--climbing" => opts.climbing = true,
+                "--climb-threshold" => {
+                    let threshold = parse_number(&arg, args.next())?;
+                    if threshold < 0.0 {
+                        return Err(format!(
+                            "--climb-threshold can't be negative: {}",
+                            threshold
+                        ));
+                    }
+                    opts.climb_threshold = Some(threshold);
+                }
+                "--radius" => opts.radius = Some(parse_radius(&arg, args.next())?),
+                "--overhead" => opts.overhead = true,
+                "--any" => opts.any = true,
+                "--empty-ok" => opts.empty_ok = true,
+                "--has" => opts.required_fields = parse_field_list(&arg, args.next())?,
+                "--fence" => {
+                    let path = args.next().ok_or("Missing value for --fence")?;
+                    let fence = fs::read_to_string(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|text| Fence::parse(&text))
+                        .map_err(|e| format!("Couldn't read fence from {}: {}", path, e))?;
+                    opts.fence = Some(fence);
+                }
+                "--category" => {
+                    opts.categories = parse_list(&arg, args.next())?
+                        .iter()
+                        .map(|name| category_from_name(name))
+                        .collect::<Result<Vec<u8>, String>>()?
+                }
+                "--country" => opts.countries = parse_list(&arg, args.next())?,
+                "--exclude-country" => opts.excluded_countries = parse_list(&arg, args.next())?,
+                "--track" => opts.track = Some(args.next().ok_or("Missing value for --track")?),
+                "--compare" => {
+                    let value = args.next().ok_or("Missing value for --compare")?;
+                    let point = match value.split_once(',') {
+                        Some((lat, lon)) => lat.trim().parse().ok().zip(lon.trim().parse().ok()),
+                        None => None,
+                    };
+                    let (lat, lon) = point
+                        .ok_or_else(|| format!("Expected LAT,LON for --compare: {}", value))?;
+                    opts.compare = Some(Point::checked(lat, lon)?);
+                }
+                "--validate" => {
+                    opts.validate = Some(args.next().ok_or("Missing value for --validate")?)
+                }
+                "--nearest-airport" => {
+                    opts.nearest_airport =
+                        Some(args.next().ok_or("Missing value for --nearest-airport")?)
+                }
+                "--histogram" => opts.histogram = true,
+                "--radar" => opts.radar = true,
+                "--count-by-altitude-band" => opts.count_by_altitude_band = true,
+                "--altitude-bands" => {
+                    let value = args.next().ok_or("Missing value for --altitude-bands")?;
+                    let bands = value
+                        .split(',')
+                        .map(|v| v.trim().parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                        .ok()
+                        .filter(|bands| bands.len() == 2 && bands[0] < bands[1])
+                        .ok_or_else(|| {
+                            format!("Expected LOW,HIGH with LOW below HIGH: {}", value)
+                        })?;
+                    opts.altitude_bands = Some([bands[0], bands[1]]);
+                }
+                "--bin-width" => {
+                    let width = parse_number(&arg, args.next())?;
+                    if width <= 0.0 {
+                        return Err(format!("--bin-width must be positive: {}", width));
+                    }
+                    opts.bin_width = Some(width);
+                }
+                "--count" => {
+                    let count = parse_count(&arg, args.next())?;
+                    if count == 0 {
+                        return Err("--count must be at least 1".to_string());
+                    }
+                    opts.count = Some(count);
+                }
+                "--best-guess" => opts.best_guess = true,
+                "--busyness" => opts.busyness = true,
+                "--busyness-radius" => {
+                    let radius = parse_number(&arg, args.next())?;
+                    if radius <= 0.0 {
+                        return Err(format!("--busyness-radius must be positive: {}", radius));
+                    }
+                    opts.busyness_radius = Some(radius);
+                }
+                "--busyness-power" => {
+                    let power = parse_number(&arg, args.next())?;
+                    if power < 0.0 {
+                        return Err(format!("--busyness-power can't be negative: {}", power));
+                    }
+                    opts.busyness_power = Some(power);
+                }
+                "--guess-weights" => {
+                    let value = args.next().ok_or("Missing value for --guess-weights")?;
+                    opts.guess_weights = GuessWeights::parse(&value)?;
+                }
+                "--verbose" => opts.verbose = true,
+                "--max-planes" => {
+                    let max_planes = parse_count(&arg, args.next())?;
+                    if max_planes == 0 {
+                        return Err("--max-planes must be at least 1".to_string());
+                    }
+                    opts.max_planes = Some(max_planes);
+                }
+                "--sort-by" => {
+                    let name = args.next().ok_or("Missing value for --sort-by")?;
+                    opts.sort_by = SortKey::from_name(&name)?;
+                }
+                "--order" => {
+                    let name = args.next().ok_or("Missing value for --order")?;
+                    opts.order = SortOrder::from_name(&name)?;
+                }
+                "--waypoints" => opts.waypoints = Some(parse_count(&arg, args.next())?),
+                "--earth-radius" => {
+                    let radius = parse_number(&arg, args.next())?;
+                    if radius <= 0.0 {
+                        return Err(format!("--earth-radius must be positive: {}", radius));
+                    }
+                    opts.earth_radius = Some(radius);
+                }
+                "--slant-range" => opts.slant_range = true,
+                "--units" => {
+                    let name = args.next().ok_or("Missing value for --units")?;
+                    opts.units = Units::from_name(&name)?;
+                }
+                "--speed-units" => {
+                    let name = args.next().ok_or("Missing value for --speed-units")?;
+                    opts.speed_units = Some(SpeedUnits::from_name(&name)?);
+                }
+                "--show-alt-delta" => opts.show_alt_delta = true,
+                "--raw-index" => opts.raw_index = true,
+                "--mid-bearing" => opts.mid_bearing = true,
+                "--contrails" => opts.contrails = true,
+                "--contrail-altitude" => {
+                    opts.contrail_altitude = Some(parse_number(&arg, args.next())?)
+                }
+                "--arrow" => {
+                    let name = args.next().ok_or("Missing value for --arrow")?;
+                    opts.arrow = Some(ArrowSource::from_name(&name)?);
+                }
+                "--no-unicode" => opts.no_unicode = true,
+                "--my-heading" => {
+                    let heading = parse_number(&arg, args.next())?;
+                    if !(0.0..=360.0).contains(&heading) {
+                        return Err(format!("--my-heading must be 0 to 360: {}", heading));
+                    }
+                    opts.my_heading = Some(heading);
+                }
+                "--distance-precision" => {
+                    opts.distance_precision = Some(parse_count(&arg, args.next())?)
+                }
+                "--distance-sig-figs" => {
+                    opts.distance_sig_figs = Some(parse_count(&arg, args.next())?)
+                }
+                "--coord-precision" => opts.coord_precision = Some(parse_count(&arg, args.next())?),
+                "--maps-url" => opts.maps_url = true,
+                "--table" => opts.table = true,
+                "--no-callsign-label" => {
+                    opts.no_callsign_label =
+                        Some(args.next().ok_or("Missing value for --no-callsign-label")?)
+                }
+                "--callsign-fallback-icao24" => opts.callsign_fallback_icao24 = true,
+                "--osm-url" => opts.osm_url = true,
+                "--locale" => {
+                    let name = args.next().ok_or("Missing value for --locale")?;
+                    opts.locale = Some(NumberLocale::from_name(&name)?);
+                }
+                "--tz" => {
+                    let name = args.next().ok_or("Missing value for --tz")?;
+                    opts.tz = TimeZoneChoice::from_name(&name)?;
+                }
+                "--format" => {
+                    let name = args.next().ok_or("Missing value for --format")?;
+                    opts.format = OutputFormat::from_name(&name)?;
+                }
+                "--json" => opts.format = OutputFormat::from_name("json")?,
+                "--metrics" => opts.format = OutputFormat::from_name("metrics")?,
+                "--credentials-file" => {
+                    let path = args.next().ok_or("Missing value for --credentials-file")?;
+                    let credentials = Credentials::from_file(&path)
+                        .map_err(|e| format!("Couldn't read credentials from {}: {}", path, e))?;
+                    opts.credentials = Some(credentials);
+                }
+                "--api-urls" => opts.api_urls = parse_list(&arg, args.next())?,
+                "--sbs" => opts.sbs = Some(args.next().ok_or("Missing value for --sbs")?),
+                "--sbs-window" => opts.sbs_window = Some(parse_count(&arg, args.next())? as u64),
+                "--min-interval" => {
+                    let secs = parse_number(&arg, args.next())?;
+                    if !secs.is_finite() {
+                        return Err(format!("--min-interval must be a finite number: {}", secs));
+                    }
+                    if secs < 0.0 {
+                        return Err(format!("--min-interval can't be negative: {}", secs));
+                    }
+                    opts.min_interval = Some(secs);
+                }
+                "--watch" => {
+                    let interval = parse_count(&arg, args.next())?;
+                    if interval == 0 {
+                        return Err("--watch needs an interval of at least 1 second".to_string());
+                    }
+                    opts.watch = Some(interval as u64);
+                }
+                "--watch-diff" => opts.watch_diff = true,
+                "--watch-diff-threshold" => {
+                    opts.watch_diff_threshold = Some(parse_number(&arg, args.next())?)
+                }
+                "--watch-until-radius" => {
+                    opts.watch_until_radius = Some(parse_radius(&arg, args.next())?)
+                }
+                "--watch-timeout" => {
+                    opts.watch_timeout = Some(parse_count(&arg, args.next())? as u64)
+                }
+                "--output" => opts.output = Some(args.next().ok_or("Missing value for --output")?),
+                "--append" => opts.append = true,
+                "--stdin-json" => opts.stdin_json = true,
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        if opts.lat.is_some() != opts.lon.is_some() {
+            return Err("--lat and --lon must be given together".to_string());
+        }
+        if opts.distance_precision.is_some() && opts.distance_sig_figs.is_some() {
+            return Err(
+                "--distance-precision and --distance-sig-figs can't be used together".to_string(),
+            );
+        }
+        if [opts.radar, opts.histogram, opts.count_by_altitude_band]
+            .iter()
+            .filter(|&&mode| mode)
+            .count()
+            > 1
+        {
+            return Err(
+                "--radar, --histogram and --count-by-altitude-band can't be used together"
+                    .to_string(),
+            );
+        }
+        if opts.compare.is_some() && opts.track.is_none() {
+            return Err("--compare requires --track".to_string());
+        }
+        if (opts.busyness_radius.is_some() || opts.busyness_power.is_some()) && !opts.busyness {
+            return Err("--busyness-radius and --busyness-power require --busyness".to_string());
+        }
+        if opts.pluscode.is_some() && (opts.lat.is_some() || opts.lon.is_some()) {
+            return Err("--pluscode can't be used with --lat/--lon".to_string());
+        }
+        if opts.contrail_altitude.is_some() && !opts.contrails {
+            return Err("--contrail-altitude requires --contrails".to_string());
+        }
+        if opts.sbs_window.is_some() && opts.sbs.is_none() {
+            return Err("--sbs-window requires --sbs".to_string());
+        }
+        if opts.table && opts.format != OutputFormat::Human {
+            return Err("--table only works with --format human".to_string());
+        }
+        if opts.any && opts.radius.is_none() {
+            return Err("--any requires --radius".to_string());
+        }
+        if opts.watch_until_radius.is_some() && opts.watch.is_none() {
+            return Err("--watch-until-radius requires --watch".to_string());
+        }
+        if opts.watch_diff && opts.watch.is_none() {
+            return Err("--watch-diff requires --watch".to_string());
+        }
+        if opts.watch_diff_threshold.is_some() && !opts.watch_diff {
+            return Err("--watch-diff-threshold requires --watch-diff".to_string());
+        }
+        if opts.watch_timeout.is_some() && opts.watch_until_radius.is_none() {
+            return Err("--watch-timeout requires --watch-until-radius".to_string());
+        }
+
+        Ok(opts)
+    }
+
+    pub fn speed_units(&self) -> SpeedUnits {
+        self.speed_units
+            .unwrap_or_else(|| self.units.default_speed_units())
+    }
+
+    // an observer given on the command line, which takes the place of stdin
+    pub(crate) fn observer(&self) -> Option<Result<Point, String>> {
+        match (self.lat, self.lon, self.bbox) {
+            (Some(lat), Some(lon), _) => Some(Point::checked(lat, lon)),
+            _ if self.pluscode.is_some() => self.pluscode.map(Ok),
+            (_, _, Some(bbox)) => Some(Ok(bbox.center())),
+            _ => None,
+        }
+    }
+
+    // with --contrails, whether the plane is high enough to be leaving a trail
+    pub(crate) fn contrail_likely(&self, state: &OpenskyState) -> bool {
+        let threshold_m = self
+            .contrail_altitude
+            .map_or(DEFAULT_CONTRAIL_ALTITUDE_M, |alt| {
+                self.units.altitude_to_m(alt)
+            });
+        self.contrails && !state.on_ground && state.altitude().is_some_and(|m| m >= threshold_m)
+    }
+
+    // a quick check ruling out planes too far away for --radius, if given
+    pub(crate) fn prefilter(&self, observer: &Point) -> Option<Prefilter> {
+        self.radius.map(|radius| {
+            Prefilter::new(
+                *observer,
+                self.units.distance_to_km(radius),
+                self.earth_radius.unwrap_or(EARTH_RADIUS_KM),
+            )
+        })
+    }
+
+    // great-circle distance in km on the configured sphere
+    pub fn distance(&self, a: &Point, b: Point) -> f64 {
+        haversine_with_radius(a, &b, self.earth_radius.unwrap_or(EARTH_RADIUS_KM))
+    }
+
+    // the distance to report for a plane this far away along the ground: the same, or
+    // with --slant-range the line of sight through the air where the altitude is known
+    pub(crate) fn range(&self, ground_km: f64, state: &OpenskyState) -> f64 {
+        match state.altitude().filter(|_| self.slant_range) {
+            Some(altitude) => ground_km.hypot(altitude / 1000.0),
+            None => ground_km,
+        }
+    }
+
+    // the checks that depend on how far away the plane is along the ground
+    pub(crate) fn within_view(&self, ground_km: f64, state: &OpenskyState) -> bool {
+        self.within_radius(self.range(ground_km, state))
+            && (!self.overhead || state.is_overhead(ground_km))
+    }
+
+    pub(crate) fn within_radius(&self, distance: f64) -> bool {
+        self.radius
+            .is_none_or(|radius| self.units.distance_from_km(distance) <= radius)
+    }
+
+    // whether a plane state survives the filters requested on the command line
+    pub fn accepts(&self, state: &OpenskyState) -> bool {
+        if let Some(min_speed) = self.min_speed {
+            match state.velocity {
+                Some(v) if v >= min_speed => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(max_age) = self.max_contact_age {
+            // measured against the snapshot, or the local clock for sources without one
+            let now = state.response_time.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            });
+            if now.saturating_sub(state.last_contact as u64) > max_age {
+                return false;
+            }
+        }
+
+        if self.descending || self.climbing {
+            let threshold = self.climb_threshold.unwrap_or(DEFAULT_CLIMB_THRESHOLD);
+            let wanted = match state.vertical_rate {
+                Some(rate) => {
+                    (self.descending && rate < -threshold) || (self.climbing && rate > threshold)
+                }
+                None => false,
+            };
+            if !wanted {
+                return false;
+            }
+        }
+
+        if let Some(fence) = &self.fence {
+            if !state.position().is_some_and(|p| fence.contains(&p)) {
+                return false;
+            }
+        }
+
+        if !self.categories.is_empty()
+            && !state
+                .category
+                .is_some_and(|category| self.categories.contains(&category))
+        {
+            return false;
+        }
+
+        let country_matches =
+            |country: &String| country.eq_ignore_ascii_case(&state.origin_country);
+        if !self.countries.is_empty() && !self.countries.iter().any(country_matches) {
+            return false;
+        }
+        if self.excluded_countries.iter().any(country_matches) {
+            return false;
+        }
+        if let Some(icao24) = &self.track {
+            if !icao24.eq_ignore_ascii_case(&state.icao24) {
+                return false;
+            }
+        }
+
+        self.required_fields
+            .iter()
+            .all(|field| state.has_field(field))
+    }
+}
+
+// a --stdin-json request: the observer plus options named like their flags, with
+// underscores or dashes, e.g. "min_speed": 50 or "country": ["Ireland", "France"]
+#[derive(Deserialize)]
+struct JsonRequest {
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(flatten)]
+    options: BTreeMap<String, Value>,
+}
+
+// turns a --stdin-json request into the equivalent flags, so it's validated exactly like argv
+pub(crate) fn json_request_args(text: &str) -> Result<Vec<String>, Error> {
+    let request: JsonRequest = serde_json::from_str(text)
+        .map_err(|e| Error::Config(format!("Invalid JSON request: {}", e)))?;
+
+    let mut args = Vec::new();
+    let numbers = [("lat", request.lat), ("lon", request.lon)];
+    for (name, value) in numbers.iter() {
+        if let Some(value) = value {
+            args.push(format!("--{}", name));
+            args.push(value.to_string());
+        }
+    }
+
+    for (name, value) in request.options {
+        let flag = format!("--{}", name.replace('_', "-"));
+        let value = match value {
+            Value::Null | Value::Bool(false) => continue,
+            Value::Bool(true) => None,
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) => Some(s),
+            Value::Array(items) => Some(
+                items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            Value::Object(_) => {
+                return Err(Error::Config(format!(
+                    "Invalid JSON request: {} can't be an object",
+                    name
+                )))
+            }
+        };
+        args.push(flag);
+        args.extend(value);
+    }
+
+    Ok(args)
+}
+
+fn parse_list(flag: &str, value: Option<String>) -> Result<Vec<String>, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    Ok(value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect())
+}
+
+fn parse_field_list(flag: &str, value: Option<String>) -> Result<Vec<String>, String> {
+    parse_list(flag, value)?
+        .into_iter()
+        .map(|field| {
+            if OPTIONAL_FIELDS.contains(&field.as_str()) {
+                Ok(field)
+            } else {
+                Err(format!(
+                    "Unknown field for {}: {} (expected one of {})",
+                    flag,
+                    field,
+                    OPTIONAL_FIELDS.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+fn parse_count(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    value
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid count for {}: {}", flag, value))
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    value
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid number for {}: {}", flag, value))
+}
+
+// a distance to look within, which has to be some real size
+fn parse_radius(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let radius = parse_number(flag, value)?;
+    if !(radius.is_finite() && radius > 0.0) {
+        return Err(format!("{} must be a positive distance: {}", flag, radius));
+    }
+    Ok(radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::haversine;
+    use crate::opensky::parse_opensky_response;
+    use crate::test_util::{read_file_bytes, synthetic_state};
+
+    #[test]
+    fn test_custom_earth_radius() {
+        let origin = Point {
+            lat: 36.12,
+            lon: -86.67,
+        };
+        let destination = Point {
+            lat: 33.94,
+            lon: -118.4,
+        };
+        let default = haversine(&origin, &destination);
+
+        let args = vec!["--earth-radius", "6378.137"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        let scaled = opts.distance(&origin, destination);
+        assert!((scaled / default - 6378.137 / 6372.8).abs() < 1e-12);
+
+        assert_eq!(Options::default().distance(&origin, destination), default);
+        let args = vec!["--earth-radius", "-1"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_parse_min_speed() {
+        let args = vec!["--min-speed".to_string(), "50.5".to_string()];
+        let opts = Options::from_args(args.into_iter()).unwrap();
+        assert_eq!(opts.min_speed, Some(50.5));
+
+        let args = vec!["--min-speed".to_string(), "fast".to_string()];
+        assert!(Options::from_args(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_min_speed_filter() {
+        let speeds = vec![Some(0.0), Some(12.0), None, Some(230.5), Some(49.9)];
+        let states: Vec<OpenskyState> = speeds
+            .into_iter()
+            .enumerate()
+            .map(|(i, velocity)| OpenskyState {
+                velocity,
+                ..synthetic_state(&i.to_string(), 0.0, 0.0)
+            })
+            .collect();
+
+        let opts = Options {
+            min_speed: Some(50.0),
+            ..Options::default()
+        };
+        let kept: Vec<&str> = states
+            .iter()
+            .filter(|s| opts.accepts(s))
+            .map(|s| s.icao24.as_str())
+            .collect();
+        assert_eq!(kept, vec!["3"]);
+
+        // without the filter nothing is dropped, including unknown velocities
+        let opts = Options::default();
+        assert_eq!(states.iter().filter(|s| opts.accepts(s)).count(), 5);
+    }
+
+    #[test]
+    fn test_parse_field_list() {
+        let args = vec!["--has".to_string(), "squawk, altitude".to_string()];
+        let opts = Options::from_args(args.into_iter()).unwrap();
+        assert_eq!(opts.required_fields, vec!["squawk", "altitude"]);
+
+        let args = vec!["--has".to_string(), "squawk,icao24".to_string()];
+        assert!(Options::from_args(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_required_fields_filter() {
+        let states = [
+            OpenskyState {
+                squawk: Some("7000".to_string()),
+                baro_altitude: Some(1000.0),
+                ..synthetic_state("both", 0.0, 0.0)
+            },
+            OpenskyState {
+                squawk: Some("7000".to_string()),
+                ..synthetic_state("squawk only", 0.0, 0.0)
+            },
+            OpenskyState {
+                geo_altitude: Some(1000.0),
+                ..synthetic_state("altitude only", 0.0, 0.0)
+            },
+            OpenskyState {
+                squawk: Some("1200".to_string()),
+                geo_altitude: Some(300.0),
+                ..synthetic_state("geo altitude", 0.0, 0.0)
+            },
+        ];
+
+        let opts = Options {
+            required_fields: vec!["squawk".to_string(), "altitude".to_string()],
+            ..Options::default()
+        };
+        let kept: Vec<&str> = states
+            .iter()
+            .filter(|s| opts.accepts(s))
+            .map(|s| s.icao24.as_str())
+            .collect();
+        assert_eq!(kept, vec!["both", "geo altitude"]);
+
+        let opts = Options {
+            required_fields: vec!["baro_altitude".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(states.iter().filter(|s| opts.accepts(s)).count(), 1);
+    }
+
+    #[test]
+    fn test_max_contact_age() {
+        let heard = |icao24, last_contact| OpenskyState {
+            last_contact,
+            response_time: Some(1595268620),
+            ..synthetic_state(icao24, 53.4, -6.2)
+        };
+        let states = [
+            heard("fresh", 1595268619),
+            heard("borderline", 1595268560),
+            heard("stale", 1595268400),
+        ];
+        let args = vec!["--max-contact-age", "60"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+
+        let kept: Vec<&str> = states
+            .iter()
+            .filter(|s| opts.accepts(s))
+            .map(|s| s.icao24.as_str())
+            .collect();
+        assert_eq!(kept, vec!["fresh", "borderline"]);
+        assert!(states.iter().all(|s| Options::default().accepts(s)));
+    }
+
+    #[test]
+    fn test_country_filters() {
+        let data = read_file_bytes("test/opensky_states_all.json");
+        let states = parse_opensky_response(data).unwrap().states;
+        let count = |opts: &Options| states.iter().filter(|s| opts.accepts(s)).count();
+
+        let opts = Options {
+            excluded_countries: vec!["united states".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(count(&opts), 4969 - 3485);
+
+        let opts = Options {
+            countries: vec!["Ireland".to_string(), "Canada".to_string()],
+            excluded_countries: vec!["CANADA".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(count(&opts), 92);
+
+        let opts = Options {
+            countries: vec!["Ireland".to_string()],
+            excluded_countries: vec!["ireland".to_string()],
+            ..Options::default()
+        };
+        assert_eq!(count(&opts), 0);
+    }
+
+    #[test]
+    fn test_vertical_rate_filters() {
+        let rates = [
+            Some(-8.5),
+            Some(-0.6),
+            None,
+            Some(0.0),
+            Some(1.2),
+            Some(12.0),
+        ];
+        let states: Vec<OpenskyState> = rates
+            .iter()
+            .enumerate()
+            .map(|(i, &rate)| OpenskyState {
+                vertical_rate: rate,
+                ..synthetic_state(&i.to_string(), 53.0, -6.0)
+            })
+            .collect();
+        let kept = |args: Vec<&str>| {
+            let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+            states
+                .iter()
+                .filter(|s| opts.accepts(s))
+                .map(|s| s.icao24.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kept(vec!["--descending"]), vec!["0"]);
+        assert_eq!(
+            kept(vec!["--descending", "--climb-threshold", "0.5"]),
+            vec!["0", "1"]
+        );
+        assert_eq!(kept(vec!["--climbing"]), vec!["4", "5"]);
+        assert_eq!(
+            kept(vec!["--climbing", "--descending", "--climb-threshold", "5"]),
+            vec!["0", "5"]
+        );
+        assert_eq!(kept(vec![]).len(), states.len());
+
+        let args = vec!["--climb-threshold", "-1"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_parse_output_options() {
+        let args = vec!["--json", "--output", "/tmp/out.json", "--append"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.format, OutputFormat::Json);
+        assert_eq!(opts.output, Some("/tmp/out.json".to_string()));
+        assert!(opts.append);
+
+        let args = vec!["--format", "csv"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.format, OutputFormat::Csv);
+
+        let args = vec!["--format", "xml"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "formats"))]
+    fn test_lite_build_output_options() {
+        for args in [vec!["--json"], vec!["--format", "csv"]] {
+            let err = Options::from_args(args.into_iter().map(String::from))
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("formats feature"), "{}", err);
+        }
+
+        let args = vec!["--format", "human", "--output", "/tmp/out.txt"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.format, OutputFormat::Human);
+        assert_eq!(opts.output, Some("/tmp/out.txt".to_string()));
+    }
+
+    #[test]
+    fn test_any_requires_radius() {
+        let args = vec!["--any"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+
+        let args = vec!["--any", "--radius", "5"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert!(opts.any);
+        assert_eq!(opts.radius, Some(5.0));
+
+        for flag in ["--radius", "--watch-until-radius"] {
+            for radius in ["-5", "0", "NaN", "inf"] {
+                let args = vec!["--any", flag, radius];
+                let err = Options::from_args(args.into_iter().map(String::from))
+                    .unwrap_err()
+                    .to_string();
+                assert!(err.contains("must be a positive distance"), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_altitude_bands() {
+        let args = vec!["--radar", "--altitude-bands", "1000, 5000"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert!(opts.radar);
+        assert_eq!(opts.altitude_bands, Some([1000.0, 5000.0]));
+
+        for bands in ["5000,1000", "1000", "1000,2000,3000", "low,high"] {
+            let args = vec!["--altitude-bands", bands];
+            assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        }
+        let args = vec!["--radar", "--histogram"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_distance_precision() {
+        let args = vec!["--distance-precision", "3"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.distance_precision, Some(3));
+        assert_eq!(opts.distance_sig_figs, None);
+
+        let args = vec!["--distance-sig-figs", "2"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.distance_sig_figs, Some(2));
+        assert_eq!(opts.distance_precision, None);
+
+        let args = vec!["--distance-precision", "3", "--distance-sig-figs", "2"];
+        let err = Options::from_args(args.into_iter().map(String::from))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("can't be used together"), "{}", err);
+
+        let args = vec!["--distance-precision", "one"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let args = vec!["--min-interval", "10.5"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.min_interval, Some(10.5));
+
+        let error = |value: &str| {
+            let args = vec!["--min-interval", value];
+            Options::from_args(args.into_iter().map(String::from))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("NaN").contains("finite"));
+        assert!(error("inf").contains("finite"));
+        assert!(error("-1").contains("negative"));
+    }
+
+    #[test]
+    fn test_track_and_compare() {
+        let args = vec!["--track", "4CA123", "--compare", "53.35, -6.26"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(
+            opts.compare,
+            Some(Point {
+                lat: 53.35,
+                lon: -6.26
+            })
+        );
+        assert!(opts.accepts(&synthetic_state("4ca123", 53.42, -6.27)));
+        assert!(!opts.accepts(&synthetic_state("4ca124", 53.42, -6.27)));
+
+        for args in [
+            vec!["--compare", "53.35,-6.26"],
+            vec!["--track", "4ca123", "--compare", "53.35"],
+            vec!["--track", "4ca123", "--compare", "95,-6.26"],
+        ] {
+            assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_watch_modes_need_watch() {
+        let args = vec!["--watch-until-radius", "2"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        let args = vec!["--watch", "30", "--watch-timeout", "600"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        let args = vec!["--watch-diff"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        let args = vec!["--watch", "30", "--watch-diff-threshold", "5"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+
+        let args = vec![
+            "--watch",
+            "30",
+            "--watch-until-radius",
+            "2",
+            "--watch-timeout",
+            "600",
+        ];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.watch, Some(30));
+        assert_eq!(opts.watch_until_radius, Some(2.0));
+        assert_eq!(opts.watch_timeout, Some(600));
+    }
+
+    #[test]
+    fn test_speed_units_independent_of_distance() {
+        let args = vec!["--units", "km", "--speed-units", "knots"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.units.distance_from_km(1.852), 1.852);
+        assert_eq!(opts.speed_units(), SpeedUnits::Knots);
+        assert!((opts.speed_units().speed_from_ms(100.0) - 194.384).abs() < 1e-3);
+
+        let args = vec!["--units", "nmi", "--speed-units", "ms"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert!((opts.units.distance_from_km(1.852) - 1.0).abs() < 1e-12);
+        assert_eq!(opts.speed_units().speed_from_ms(100.0), 100.0);
+
+        // without --speed-units the speed follows the distance units
+        let defaults: Vec<(SpeedUnits, f64)> = [Units::Km, Units::Mi, Units::Nmi]
+            .iter()
+            .map(|&units| {
+                let speed = Options {
+                    units,
+                    ..Options::default()
+                }
+                .speed_units();
+                (speed, speed.speed_from_ms(100.0))
+            })
+            .collect();
+        assert_eq!(defaults[0], (SpeedUnits::Kmh, 360.0));
+        assert_eq!(defaults[1].0, SpeedUnits::Mph);
+        assert!((defaults[1].1 - 223.694).abs() < 1e-3);
+        assert_eq!(defaults[2].0, SpeedUnits::Knots);
+
+        let args = vec!["--speed-units", "furlongs"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_radius_in_display_units() {
+        let opts = Options {
+            units: Units::Nmi,
+            radius: Some(10.0),
+            ..Options::default()
+        };
+        assert!(opts.within_radius(18.5));
+        assert!(!opts.within_radius(18.6));
+    }
+
+    #[test]
+    fn test_observer_from_args() {
+        let args = vec!["--bbox", "50,-10,54,-6"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(
+            opts.observer(),
+            Some(Ok(Point {
+                lat: 52.0,
+                lon: -8.0
+            }))
+        );
+
+        let args = vec!["--bbox", "50,-10,54,-6", "--lat", "53.21", "--lon", "-6.18"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(
+            opts.observer(),
+            Some(Ok(Point {
+                lat: 53.21,
+                lon: -6.18
+            }))
+        );
+
+        // a plus code wins over the box's centre too
+        let args = vec!["--bbox", "50,-10,54,-6", "--pluscode", "9C5M8QJ8+2V"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        let observer = opts.observer().unwrap().unwrap();
+        assert!((observer.lat - 53.3301).abs() < 1e-3 && (observer.lon + 6.2328).abs() < 1e-3);
+
+        let args = vec![
+            "--pluscode",
+            "9C5M8QJ8+2V",
+            "--lat",
+            "53.21",
+            "--lon",
+            "-6.18",
+        ];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+        let args = vec!["--pluscode", "8QJ8+2V"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+
+        let args = vec!["--lat", "53.21"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+
+        assert_eq!(Options::default().observer(), None);
+    }
+
+    #[test]
+    fn test_category_filter() {
+        let args = vec!["--category", "Heavy, rotorcraft"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(opts.categories, vec![6, 8]);
+
+        let state = |category| OpenskyState {
+            category,
+            ..synthetic_state("4ca123", 53.0, -6.0)
+        };
+        assert!(opts.accepts(&state(Some(8))));
+        assert!(!opts.accepts(&state(Some(2))));
+        assert!(!opts.accepts(&state(None)));
+        assert!(Options::default().accepts(&state(None)));
+
+        let args = vec!["--category", "spaceship"];
+        assert!(Options::from_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_slant_range() {
+        let state = OpenskyState {
+            baro_altitude: Some(4000.0),
+            ..synthetic_state("4ca123", 53.0, -6.0)
+        };
+        let opts = Options {
+            slant_range: true,
+            ..Options::default()
+        };
+        assert!((opts.range(3.0, &state) - 5.0).abs() < 1e-12);
+        assert_eq!(Options::default().range(3.0, &state), 3.0);
+
+        let grounded = synthetic_state("4ca124", 53.0, -6.0);
+        assert_eq!(opts.range(3.0, &grounded), 3.0);
+
+        // the radius applies to the slant range
+        let opts = Options {
+            radius: Some(4.0),
+            ..opts
+        };
+        assert!(!opts.within_view(3.0, &state));
+        assert!(opts.within_view(3.0, &grounded));
+    }
+
+    #[test]
+    fn test_json_request() {
+        let request = r#"{
+            "lat": 53.21,
+            "lon": -6.18,
+            "units": "nmi",
+            "count": 3,
+            "min_speed": 50,
+            "country": ["Ireland", "United Kingdom"],
+            "bbox": [52, -8, 54, -4],
+            "overhead": true,
+            "append": false,
+            "output": null
+        }"#;
+        let args = json_request_args(request).unwrap();
+        let opts = Options::from_args(args.into_iter()).unwrap();
+        assert_eq!(
+            opts,
+            Options {
+                lat: Some(53.21),
+                lon: Some(-6.18),
+                units: Units::Nmi,
+                count: Some(3),
+                min_speed: Some(50.0),
+                countries: vec!["Ireland".to_string(), "United Kingdom".to_string()],
+                bbox: Some(BoundingBox::parse("52,-8,54,-4").unwrap()),
+                overhead: true,
+                ..Options::default()
+            }
+        );
+
+        // the usual validation applies
+        let args = json_request_args(r#"{"lat": 53.21}"#).unwrap();
+        assert!(Options::from_args(args.into_iter()).is_err());
+        let args = json_request_args(r#"{"colour": "red"}"#).unwrap();
+        assert!(Options::from_args(args.into_iter()).is_err());
+        assert!(json_request_args(r#"{"units": {"distance": "km"}}"#).is_err());
+        assert!(json_request_args("53.21 N\n6.18 W").is_err());
+    }
+}
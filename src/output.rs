@@ -0,0 +1,1887 @@
+use crate::coords::{Point, DEFAULT_COORD_PRECISION};
+use crate::geo::{
+    along_track_distance, bearing, cross_track_distance, great_circle_waypoints, midpoint_bearing,
+    EARTH_RADIUS_KM,
+};
+use crate::opensky::{OpenskyState, Validation};
+use crate::options::{ArrowSource, Options, OutputFormat};
+use crate::places::Place;
+use crate::timestamps::format_timestamp;
+#[cfg(feature = "formats")]
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::process;
+
+// a plane along with what we've worked out about it relative to the observer
+pub(crate) struct Sighting<'a> {
+    // the name given to the observer on stdin, if any
+    pub(crate) observer_label: Option<&'a str>,
+    // where the distance is measured from, for the --maps-url directions
+    pub(crate) observer: Option<Point>,
+    pub(crate) state: &'a OpenskyState,
+    pub(crate) distance: f64,
+    // degrees clockwise from north, from the observer to the plane
+    pub(crate) bearing: Option<f64>,
+    // the same, but at the halfway point of the path, with --mid-bearing
+    pub(crate) mid_bearing: Option<f64>,
+    pub(crate) flyover: Option<Flyover>,
+    pub(crate) overhead: bool,
+    pub(crate) contrail_likely: bool,
+    pub(crate) waypoints: Option<Vec<Point>>,
+    // unix time that ages are measured against
+    pub(crate) now: u64,
+    // how the plane compares with the last --watch --table poll, from the second poll on
+    pub(crate) watch_status: Option<WatchStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum WatchStatus {
+    New,
+    Continuing,
+    // listed for one more poll after leaving, then dropped
+    Departed,
+}
+
+// where the plane's current course takes it relative to the observer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Flyover {
+    pub(crate) cross_track_km: f64,
+    pub(crate) approaching: bool,
+    // how long until it's closest, if it's approaching and its speed is known
+    pub(crate) closest_in_secs: Option<f64>,
+}
+
+impl<'a> Sighting<'a> {
+    pub(crate) fn new(
+        opts: &Options,
+        observer: &Point,
+        state: &'a OpenskyState,
+        distance: f64,
+        now: u64,
+    ) -> Self {
+        let radius = opts.earth_radius.unwrap_or(EARTH_RADIUS_KM);
+        let flyover = match (state.position(), state.true_track) {
+            (Some(plane_pos), Some(track)) => {
+                let along_km = along_track_distance(observer, &plane_pos, track, radius);
+                Some(Flyover {
+                    cross_track_km: cross_track_distance(observer, &plane_pos, track, radius).abs(),
+                    approaching: (bearing(&plane_pos, observer) - track).to_radians().cos() > 0.0,
+                    closest_in_secs: state
+                        .velocity
+                        .filter(|&v| v > 0.0 && along_km > 0.0)
+                        .map(|v| along_km * 1000.0 / v),
+                })
+            }
+            _ => None,
+        };
+        let waypoints = opts
+            .waypoints
+            .zip(state.position())
+            .map(|(n, plane_pos)| great_circle_waypoints(observer, &plane_pos, n));
+
+        Sighting {
+            observer_label: None,
+            observer: Some(*observer),
+            state,
+            distance,
+            bearing: state
+                .position()
+                .map(|plane_pos| bearing(observer, &plane_pos)),
+            mid_bearing: state
+                .position()
+                .filter(|_| opts.mid_bearing)
+                .map(|plane_pos| midpoint_bearing(observer, &plane_pos)),
+            flyover,
+            // --slant-range may have changed the distance, but elevation needs ground distance
+            overhead: state
+                .position()
+                .is_some_and(|plane_pos| state.is_overhead(opts.distance(observer, plane_pos))),
+            contrail_likely: opts.contrail_likely(state),
+            waypoints,
+            now,
+            watch_status: None,
+        }
+    }
+}
+
+// the shape of each JSON record, so consumers can tell releases apart; bump the major
+// version when a field is removed, renamed or changes meaning, and the minor version
+// when one is added
+#[cfg(feature = "formats")]
+pub(crate) const JSON_SCHEMA_VERSION: &str = "1.6";
+
+#[cfg(feature = "formats")]
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    schema_version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    observer: Option<&'a str>,
+    #[serde(flatten)]
+    state: &'a OpenskyState,
+    // canonical values alongside the same quantities in the requested units
+    distance_km: f64,
+    distance: f64,
+    unit: &'static str,
+    altitude_m: Option<f64>,
+    altitude: Option<f64>,
+    altitude_unit: &'static str,
+    speed: Option<f64>,
+    speed_unit: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cross_track_km: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cross_track: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    approaching: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    closest_approach_secs: Option<f64>,
+    overhead: bool,
+    // only included with --contrails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contrail_likely: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    altitude_delta: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_index: Option<usize>,
+    // both bearings are only included with --mid-bearing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bearing: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mid_bearing: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    waypoints: Option<&'a [Point]>,
+}
+
+#[cfg(feature = "formats")]
+const DISTANCE_METRIC: &str = "nearest_plane_distance_km";
+#[cfg(feature = "formats")]
+const VISIBLE_METRIC: &str = "nearest_plane_visible_planes";
+
+#[cfg(feature = "formats")]
+// the header names the units of the converted columns, e.g. distance_nmi
+fn csv_header(opts: &Options) -> String {
+    format!(
+        "icao24,callsign,origin_country,latitude,longitude,baro_altitude_{alt},\
+         geo_altitude_{alt},velocity_{speed},true_track,squawk,distance_{dist}",
+        alt = opts.units.altitude_label(),
+        speed = opts.speed_units().name(),
+        dist = opts.units.distance_label()
+    )
+}
+
+// renders the chosen planes in order, out of `visible` that passed the filters; CSV
+// shares one header between them, and metrics add a gauge for the visible count
+pub(crate) fn render_result(opts: &Options, sightings: &[Sighting], visible: usize) -> String {
+    if opts.table && opts.format == OutputFormat::Human {
+        // borders and highlighting only help on a terminal; files and pipes get plain columns
+        let terminal = opts.output.is_none() && io::stdout().is_terminal();
+        return render_table(opts, sightings, terminal);
+    }
+
+    let mut out = String::new();
+    #[cfg(feature = "formats")]
+    match opts.format {
+        OutputFormat::Csv => {
+            out.push_str(&csv_header(opts));
+            out.push('\n');
+        }
+        OutputFormat::Metrics => out.push_str(&format!(
+            "# HELP {} Great-circle distance from the observer to the plane.\n\
+             # TYPE {} gauge\n",
+            DISTANCE_METRIC, DISTANCE_METRIC
+        )),
+        _ => {}
+    }
+    for sighting in sightings {
+        out.push_str(&render_sighting(opts, sighting));
+    }
+    #[cfg(feature = "formats")]
+    if opts.format == OutputFormat::Metrics {
+        out.push_str(&format!(
+            "# HELP {} Planes with known positions that passed the filters.\n\
+             # TYPE {} gauge\n\
+             {} {}\n",
+            VISIBLE_METRIC, VISIBLE_METRIC, VISIBLE_METRIC, visible
+        ));
+    }
+    #[cfg(not(feature = "formats"))]
+    let _ = visible;
+    out
+}
+
+fn render_sighting(opts: &Options, sighting: &Sighting) -> String {
+    let units = opts.units;
+    let speed_units = opts.speed_units();
+    let (state, distance) = (sighting.state, sighting.distance);
+    let waypoints = sighting.waypoints.as_deref();
+
+    match opts.format {
+        OutputFormat::Human => {
+            let precision = opts.coord_precision.unwrap_or(DEFAULT_COORD_PRECISION);
+            let mut out = match sighting.observer_label {
+                Some(label) => format!("Observer: {}\n", label),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "Result: {:?} with distance {} {}.\n",
+                state,
+                localize(
+                    opts,
+                    format_distance(opts, units.distance_from_km(distance))
+                ),
+                units.distance_label()
+            ));
+            out.push_str(&format!("Callsign: {}\n", callsign_label(opts, state)));
+            if let Some(plane_pos) = state.position() {
+                out.push_str(&format!("Plane position: {:.*}\n", precision, plane_pos));
+            }
+            if let Some(ms) = state.velocity {
+                out.push_str(&format!(
+                    "Speed: {} {}\n",
+                    localize(opts, format!("{:.0}", speed_units.speed_from_ms(ms))),
+                    speed_units.speed_label()
+                ));
+            }
+            out.push_str(&format!(
+                "Position time: {}\nLast contact: {}\n",
+                format_timestamp(state.time_position, sighting.now, opts.tz),
+                format_timestamp(Some(state.last_contact), sighting.now, opts.tz)
+            ));
+            if let Some(time) = state.response_time {
+                out.push_str(&format!(
+                    "Data time: {}\n",
+                    format_timestamp(Some(time as usize), sighting.now, opts.tz)
+                ));
+            }
+            let direction = match opts.arrow {
+                Some(ArrowSource::Bearing) => sighting.bearing.map(|deg| (deg, "bearing")),
+                Some(ArrowSource::Track) => state.true_track.map(|deg| (deg, "track")),
+                None => None,
+            };
+            if let Some((initial, mid)) = sighting.bearing.zip(sighting.mid_bearing) {
+                out.push_str(&format!(
+                    "Bearing: {:.1}° initially, {:.1}° at mid-path\n",
+                    initial, mid
+                ));
+            }
+            if let Some((degrees, source)) = direction {
+                out.push_str(&format!(
+                    "Direction: {} {:.0}° ({})\n",
+                    arrow(degrees, !opts.no_unicode),
+                    degrees,
+                    source
+                ));
+            }
+            if let Some(clock) = clock_position(opts, sighting) {
+                out.push_str(&format!("Traffic at your {} o'clock.\n", clock));
+            }
+            if let Some(delta) = state.altitude_delta().filter(|_| opts.show_alt_delta) {
+                out.push_str(&format!(
+                    "Altitude delta (geo - baro): {} {}\n",
+                    localize(opts, format!("{:.0}", units.altitude_from_m(delta))),
+                    units.altitude_label()
+                ));
+            }
+            if let Some(i) = state.response_index.filter(|_| opts.raw_index) {
+                out.push_str(&format!("Response index: {}\n", i));
+            }
+            if sighting.overhead {
+                out.push_str("[OVERHEAD] Look straight up!\n");
+            }
+            if sighting.contrail_likely {
+                out.push_str("[CONTRAIL-LIKELY] High enough to leave a visible trail.\n");
+            }
+            if let Some(flyover) = sighting.flyover {
+                let verb = if flyover.approaching {
+                    "Will pass"
+                } else {
+                    "Moving away, passed"
+                };
+                let when = match flyover.closest_in_secs {
+                    Some(secs) if secs < 90.0 => format!(" in about {:.0} s", secs),
+                    Some(secs) => format!(" in about {:.0} min", secs / 60.0),
+                    None => String::new(),
+                };
+                out.push_str(&format!(
+                    "{} ~{} {} to your side{}.\n",
+                    verb,
+                    localize(
+                        opts,
+                        format!("{:.1}", units.distance_from_km(flyover.cross_track_km))
+                    ),
+                    units.distance_label(),
+                    when
+                ));
+            }
+            // the rest of the result stands without it, so just say what's missing
+            if let Some(missing) = state.position().and(missing_for_projection(state)) {
+                out.push_str(&format!("Projection unavailable: missing {}.\n", missing));
+            }
+            if let Some(plane_pos) = state.position() {
+                out.push_str(&map_links(opts, sighting.observer.as_ref(), &plane_pos));
+            }
+            if let Some(waypoints) = waypoints {
+                out.push_str("Path waypoints:\n");
+                for point in waypoints {
+                    out.push_str(&format!("{:.*}\n", precision, point));
+                }
+            }
+            out
+        }
+        #[cfg(feature = "formats")]
+        OutputFormat::Json => {
+            let result = JsonResult {
+                schema_version: JSON_SCHEMA_VERSION,
+                observer: sighting.observer_label,
+                state,
+                distance_km: distance,
+                distance: units.distance_from_km(distance),
+                unit: units.distance_label(),
+                altitude_m: state.altitude(),
+                altitude: state.altitude().map(|m| units.altitude_from_m(m)),
+                altitude_unit: units.altitude_label(),
+                speed: state.velocity.map(|ms| speed_units.speed_from_ms(ms)),
+                speed_unit: speed_units.speed_label(),
+                cross_track_km: sighting.flyover.map(|f| f.cross_track_km),
+                cross_track: sighting
+                    .flyover
+                    .map(|f| units.distance_from_km(f.cross_track_km)),
+                approaching: sighting.flyover.map(|f| f.approaching),
+                closest_approach_secs: sighting.flyover.and_then(|f| f.closest_in_secs),
+                overhead: sighting.overhead,
+                contrail_likely: Some(sighting.contrail_likely).filter(|_| opts.contrails),
+                altitude_delta: state
+                    .altitude_delta()
+                    .filter(|_| opts.show_alt_delta)
+                    .map(|m| units.altitude_from_m(m)),
+                clock: clock_position(opts, sighting),
+                response_index: state.response_index.filter(|_| opts.raw_index),
+                bearing: sighting.bearing.filter(|_| sighting.mid_bearing.is_some()),
+                mid_bearing: sighting.mid_bearing,
+                waypoints,
+            };
+            format!("{}\n", serde_json::to_string(&result).unwrap())
+        }
+        #[cfg(feature = "formats")]
+        OutputFormat::Csv => {
+            let altitude = |m| format_distance(opts, units.altitude_from_m(m));
+            let row = [
+                csv_field(&state.icao24),
+                csv_field(state.callsign.trim()),
+                csv_field(&state.origin_country),
+                optional_field(state.latitude),
+                optional_field(state.longitude),
+                optional_field(state.baro_altitude.map(altitude)),
+                optional_field(state.geo_altitude.map(altitude)),
+                optional_field(
+                    state
+                        .velocity
+                        .map(|ms| format_distance(opts, speed_units.speed_from_ms(ms))),
+                ),
+                optional_field(state.true_track),
+                optional_field(state.squawk.as_ref()),
+                format_distance(opts, units.distance_from_km(distance)),
+            ];
+            format!("{}\n", row.join(","))
+        }
+        #[cfg(feature = "formats")]
+        OutputFormat::Metrics => {
+            let mut labels = vec![
+                ("icao24", state.icao24.as_str()),
+                ("callsign", state.callsign.trim()),
+                ("origin_country", state.origin_country.as_str()),
+            ];
+            if let Some(label) = sighting.observer_label {
+                labels.push(("observer", label));
+            }
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, metric_label_value(value)))
+                .collect();
+            format!("{}{{{}}} {}\n", DISTANCE_METRIC, labels.join(","), distance)
+        }
+    }
+}
+
+// the --nearest-airport result, e.g. "Nearest place: Dublin Airport with distance 8.9 km."
+pub(crate) fn render_nearest_place(opts: &Options, place: &Place, distance: f64) -> String {
+    let units = opts.units;
+    format!(
+        "Nearest place: {} with distance {} {}.\n",
+        place.name,
+        localize(
+            opts,
+            format_distance(opts, units.distance_from_km(distance))
+        ),
+        units.distance_label()
+    )
+}
+
+// one line per observer for the same plane, e.g.
+// "Home (53.2100, -6.1800): 12.3 km, bearing 45°"
+pub(crate) fn render_comparison(
+    opts: &Options,
+    observers: &[(Option<&str>, Point)],
+    sightings: &[Option<(f64, &OpenskyState)>],
+) -> String {
+    let units = opts.units;
+    let precision = opts.coord_precision.unwrap_or(DEFAULT_COORD_PRECISION);
+    let mut out = match sightings.iter().flatten().next() {
+        Some((_, state)) => format!(
+            "Plane {} ({}):\n",
+            state.icao24,
+            callsign_label(opts, state)
+        ),
+        None => String::new(),
+    };
+
+    for (i, ((label, observer), sighting)) in observers.iter().zip(sightings).enumerate() {
+        let name = label.map_or_else(|| format!("Observer {}", i + 1), str::to_string);
+        out.push_str(&format!("{} ({:.*}): ", name, precision, observer));
+        match sighting.and_then(|(distance, state)| Some((distance, state.position()?))) {
+            Some((distance, plane_pos)) => {
+                let degrees = bearing(observer, &plane_pos);
+                let direction = match opts.arrow {
+                    Some(_) => format!(" {}", arrow(degrees, !opts.no_unicode)),
+                    None => String::new(),
+                };
+                out.push_str(&format!(
+                    "{} {}, bearing {:.0}°{}\n",
+                    localize(
+                        opts,
+                        format_distance(opts, units.distance_from_km(distance))
+                    ),
+                    units.distance_label(),
+                    degrees,
+                    direction
+                ));
+            }
+            None => out.push_str("not visible\n"),
+        }
+    }
+    out
+}
+
+// ANSI escapes for highlighting --watch --table rows
+const BOLD: &str = "\x1b[1m";
+const DIM_STRUCK: &str = "\x1b[2;9m";
+const RESET: &str = "\x1b[0m";
+
+// --table: one row per plane, numbers right-aligned, columns as wide as their contents.
+// With --watch a status column marks the planes new since the last poll and those gone
+// from it, which a terminal also shows in bold and struck through.
+pub(crate) fn render_table(opts: &Options, sightings: &[Sighting], terminal: bool) -> String {
+    let units = opts.units;
+    let speed_units = opts.speed_units();
+    let mut header = vec![
+        "Callsign".to_string(),
+        format!("Distance ({})", units.distance_label()),
+        "Bearing".to_string(),
+        format!("Altitude ({})", units.altitude_label()),
+        format!("Speed ({})", speed_units.speed_label()),
+        "Country".to_string(),
+    ];
+    let mut numeric = vec![false, true, true, true, true, false];
+    if opts.watch.is_some() {
+        header.push("Status".to_string());
+        numeric.push(false);
+    }
+    let unknown = || "-".to_string();
+
+    let rows: Vec<Vec<String>> = sightings
+        .iter()
+        .map(|sighting| {
+            let state = sighting.state;
+            let mut row = vec![
+                callsign_label(opts, state).to_string(),
+                localize(
+                    opts,
+                    format_distance(opts, units.distance_from_km(sighting.distance)),
+                ),
+                sighting
+                    .bearing
+                    .map_or_else(unknown, |deg| format!("{:.0}°", deg)),
+                state.altitude().map_or_else(unknown, |m| {
+                    localize(opts, format!("{:.0}", units.altitude_from_m(m)))
+                }),
+                state.velocity.map_or_else(unknown, |ms| {
+                    localize(opts, format!("{:.0}", speed_units.speed_from_ms(ms)))
+                }),
+                state.origin_country.clone(),
+            ];
+            if opts.watch.is_some() {
+                row.push(
+                    match sighting.watch_status {
+                        Some(WatchStatus::New) => "new",
+                        Some(WatchStatus::Departed) => "departed",
+                        Some(WatchStatus::Continuing) | None => "",
+                    }
+                    .to_string(),
+                );
+            }
+            row
+        })
+        .collect();
+
+    let mut widths = vec![0; header.len()];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |row: &[String], right_align: bool| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((cell, &width), &is_number)| {
+                if is_number && right_align {
+                    format!("{:>width$}", cell, width = width)
+                } else {
+                    format!("{:<width$}", cell, width = width)
+                }
+            })
+            .collect();
+        if terminal {
+            format!("| {} |\n", cells.join(" | "))
+        } else {
+            format!("{}\n", cells.join("  ").trim_end())
+        }
+    };
+    let rule: String = if terminal {
+        let dashes: Vec<String> = widths.iter().map(|&w| "-".repeat(w + 2)).collect();
+        format!("+{}+\n", dashes.join("+"))
+    } else {
+        String::new()
+    };
+
+    let mut out = rule.clone();
+    out.push_str(&line(&header, false));
+    out.push_str(&rule);
+    for (row, sighting) in rows.iter().zip(sightings) {
+        let style = match sighting.watch_status.filter(|_| terminal) {
+            Some(WatchStatus::New) => BOLD,
+            Some(WatchStatus::Departed) => DIM_STRUCK,
+            _ => "",
+        };
+        if style.is_empty() {
+            out.push_str(&line(row, true));
+        } else {
+            out.push_str(&format!(
+                "{}{}{}\n",
+                style,
+                line(row, true).trim_end(),
+                RESET
+            ));
+        }
+    }
+    if !rows.is_empty() {
+        out.push_str(&rule);
+    }
+    out
+}
+
+// the callsign to print, or what stands in for a missing one: the icao24 with
+// --callsign-fallback-icao24, or else --no-callsign-label
+fn callsign_label<'a>(opts: &'a Options, state: &'a OpenskyState) -> &'a str {
+    match state.callsign.trim() {
+        "" if opts.callsign_fallback_icao24 => &state.icao24,
+        "" => opts
+            .no_callsign_label
+            .as_deref()
+            .unwrap_or(DEFAULT_NO_CALLSIGN_LABEL),
+        callsign => callsign,
+    }
+}
+
+// where the plane is relative to --my-heading, as on a clock face with 12 dead ahead
+fn clock_position(opts: &Options, sighting: &Sighting) -> Option<u32> {
+    let heading = opts.my_heading?;
+    sighting.bearing.map(|bearing| clock_hour(heading, bearing))
+}
+
+pub(crate) fn clock_hour(heading: f64, bearing: f64) -> u32 {
+    let relative = (bearing - heading).rem_euclid(360.0);
+    match ((relative + 15.0) / 30.0) as u32 % 12 {
+        0 => 12,
+        hour => hour,
+    }
+}
+
+// what's lacking to project the plane's closest approach: its course for where, and its
+// speed for when
+fn missing_for_projection(state: &OpenskyState) -> Option<&'static str> {
+    match (state.true_track, state.velocity) {
+        (None, None) => Some("track and velocity"),
+        (None, Some(_)) => Some("track"),
+        (Some(_), None) => Some("velocity"),
+        (Some(_), Some(_)) => None,
+    }
+}
+
+const UNICODE_ARROWS: [char; 8] = ['↑', '↗', '→', '↘', '↓', '↙', '←', '↖'];
+const ASCII_ARROWS: [char; 8] = ['^', '/', '>', '\\', 'v', '/', '<', '\\'];
+
+// the nearest of the eight compass arrows to a direction in degrees clockwise from north
+pub(crate) fn arrow(degrees: f64, unicode: bool) -> char {
+    let sector = ((degrees.rem_euclid(360.0) + 22.5) / 45.0) as usize % 8;
+    if unicode {
+        UNICODE_ARROWS[sector]
+    } else {
+        ASCII_ARROWS[sector]
+    }
+}
+
+// decimal places for distances unless asked otherwise
+const DEFAULT_DISTANCE_PRECISION: usize = 1;
+
+// a number for the human result in the --locale's separators; JSON and CSV keep the '.'
+fn localize(opts: &Options, number: String) -> String {
+    match opts.locale {
+        Some(locale) => locale.format(&number),
+        None => number,
+    }
+}
+
+// a distance for display, to the requested decimal places or significant figures
+fn format_distance(opts: &Options, value: f64) -> String {
+    let figures = match opts.distance_sig_figs {
+        Some(figures) => figures as i32,
+        None => {
+            let precision = opts
+                .distance_precision
+                .unwrap_or(DEFAULT_DISTANCE_PRECISION);
+            return format!("{:.*}", precision, value);
+        }
+    };
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+
+    let decimals = figures - 1 - value.abs().log10().floor() as i32;
+    if decimals >= 0 {
+        format!("{:.*}", decimals as usize, value)
+    } else {
+        // more digits before the point than figures wanted: round them to zeros
+        let scale = 10f64.powi(-decimals);
+        format!("{}", (value / scale).round() * scale)
+    }
+}
+
+// --radar map size in characters; twice as wide as tall, as terminal cells are
+const RADAR_HALF_HEIGHT: usize = 10;
+const RADAR_HALF_WIDTH: usize = 2 * RADAR_HALF_HEIGHT;
+
+// the --radar symbol for a plane at this altitude, in --units, given the band limits
+pub(crate) fn altitude_symbol(altitude: Option<f64>, bands: [f64; 2]) -> char {
+    match altitude {
+        Some(altitude) if altitude < bands[0] => 'L',
+        Some(altitude) if altitude < bands[1] => 'M',
+        Some(_) => 'H',
+        None => '*',
+    }
+}
+
+// the planes on a map centred on the observer, north up, with the edge at --radius or
+// the furthest plane; where planes share a cell the nearest is shown
+pub(crate) fn render_radar(
+    opts: &Options,
+    observer: &Point,
+    results: &[(f64, &OpenskyState)],
+) -> String {
+    let units = opts.units;
+    let bands = opts
+        .altitude_bands
+        .unwrap_or_else(|| units.default_altitude_bands());
+    let furthest = results.iter().map(|&(d, _)| d).fold(0.0, f64::max);
+    let edge_km = opts.radius.map_or(furthest, |r| units.distance_to_km(r));
+    let edge_km = if edge_km > 0.0 { edge_km } else { 1.0 };
+
+    let (rows, cols) = (2 * RADAR_HALF_HEIGHT + 1, 2 * RADAR_HALF_WIDTH + 1);
+    let mut grid = vec![vec![' '; cols]; rows];
+    for (row, line) in grid.iter_mut().enumerate() {
+        for (col, cell) in line.iter_mut().enumerate() {
+            let y = (row as f64 - RADAR_HALF_HEIGHT as f64) / RADAR_HALF_HEIGHT as f64;
+            let x = (col as f64 - RADAR_HALF_WIDTH as f64) / RADAR_HALF_WIDTH as f64;
+            if (x.hypot(y) - 1.0).abs() < 0.5 / RADAR_HALF_HEIGHT as f64 {
+                *cell = '.';
+            }
+        }
+    }
+    grid[RADAR_HALF_HEIGHT][RADAR_HALF_WIDTH] = '+';
+
+    // furthest first, so nearer planes are drawn over them
+    for &(distance, state) in results.iter().rev() {
+        let plane_pos = match state.position() {
+            Some(plane_pos) if distance <= edge_km => plane_pos,
+            _ => continue,
+        };
+        let theta = bearing(observer, &plane_pos).to_radians();
+        let r = distance / edge_km;
+        let col = RADAR_HALF_WIDTH as f64 + (r * theta.sin() * RADAR_HALF_WIDTH as f64).round();
+        let row = RADAR_HALF_HEIGHT as f64 - (r * theta.cos() * RADAR_HALF_HEIGHT as f64).round();
+        grid[row as usize][col as usize] =
+            altitude_symbol(state.altitude().map(|m| units.altitude_from_m(m)), bands);
+    }
+
+    let mut out: String = grid
+        .iter()
+        .map(|line| format!("{}\n", line.iter().collect::<String>().trim_end()))
+        .collect();
+    let alt = units.altitude_label();
+    out.push_str(&format!(
+        "+ you, edge {} {}; L below {} {alt}, M below {} {alt}, H above, * altitude unknown\n",
+        localize(opts, format_distance(opts, units.distance_from_km(edge_km))),
+        units.distance_label(),
+        bands[0],
+        bands[1],
+        alt = alt
+    ));
+    out
+}
+
+// the longest bar in a histogram, in characters
+const HISTOGRAM_WIDTH: usize = 50;
+
+// how many distances fall in each band [i * width, (i + 1) * width), up to the furthest
+pub(crate) fn histogram(distances: &[f64], bin_width: f64) -> Vec<usize> {
+    let mut counts = Vec::new();
+    for &distance in distances {
+        let bin = (distance / bin_width) as usize;
+        if bin >= counts.len() {
+            counts.resize(bin + 1, 0);
+        }
+        counts[bin] += 1;
+    }
+    counts
+}
+
+// one line per band, e.g. "  10-20 km | ####### 7"
+pub(crate) fn render_histogram(opts: &Options, counts: &[usize], bin_width: f64) -> String {
+    // as many decimals as the bin width needs, so 0.1 km bins don't show float noise
+    let decimals = (0..6)
+        .find(|&d| {
+            let scaled = bin_width * 10f64.powi(d);
+            (scaled - scaled.round()).abs() < 1e-9
+        })
+        .unwrap_or(6) as usize;
+    let labels: Vec<String> = (0..counts.len())
+        .map(|i| {
+            format!(
+                "{:.*}-{:.*} {}",
+                decimals,
+                i as f64 * bin_width,
+                decimals,
+                (i + 1) as f64 * bin_width,
+                opts.units.distance_label()
+            )
+        })
+        .collect();
+    render_bars(&labels, counts)
+}
+
+// how many planes are on the ground, below, between and above the --altitude-bands
+// limits, and without an altitude at all
+pub(crate) fn altitude_band_counts(opts: &Options, results: &[(f64, &OpenskyState)]) -> [usize; 5] {
+    let bands = opts
+        .altitude_bands
+        .unwrap_or_else(|| opts.units.default_altitude_bands());
+    let mut counts = [0; 5];
+    for (_, state) in results {
+        let altitude = state.altitude().map(|m| opts.units.altitude_from_m(m));
+        let band = match altitude_symbol(altitude, bands) {
+            _ if state.on_ground => 0,
+            'L' => 1,
+            'M' => 2,
+            'H' => 3,
+            _ => 4,
+        };
+        counts[band] += 1;
+    }
+    counts
+}
+
+const DEFAULT_NO_CALLSIGN_LABEL: &str = "(no callsign)";
+
+// the most --validate lists before summing up the rest
+const MAX_LISTED_SURPRISES: usize = 20;
+
+// e.g. "Parsed 4969 plane states, skipped 0.\nNo schema surprises.\n"
+pub(crate) fn render_validation(validation: &Validation) -> String {
+    let mut out = format!(
+        "Parsed {} plane states, skipped {}.\n",
+        validation.parsed, validation.skipped
+    );
+    if validation.surprises.is_empty() {
+        out.push_str("No schema surprises.\n");
+        return out;
+    }
+
+    out.push_str(&format!(
+        "Schema surprises: {}\n",
+        validation.surprises.len()
+    ));
+    for surprise in validation.surprises.iter().take(MAX_LISTED_SURPRISES) {
+        out.push_str(&format!("  {}\n", surprise));
+    }
+    if validation.surprises.len() > MAX_LISTED_SURPRISES {
+        out.push_str(&format!(
+            "  ...and {} more\n",
+            validation.surprises.len() - MAX_LISTED_SURPRISES
+        ));
+    }
+    out
+}
+
+// close enough on OpenStreetMap to pick out roads and towns around the plane
+const OSM_ZOOM: u32 = 12;
+
+// links to the plane on Google Maps with --maps-url, with directions from the observer,
+// and on OpenStreetMap with --osm-url
+fn map_links(opts: &Options, observer: Option<&Point>, plane_pos: &Point) -> String {
+    let precision = opts.coord_precision.unwrap_or(DEFAULT_COORD_PRECISION);
+    let coords = |p: &Point| format!("{:.*},{:.*}", precision, p.lat, precision, p.lon);
+    let mut out = String::new();
+
+    if opts.maps_url {
+        out.push_str(&format!(
+            "Map: https://www.google.com/maps/search/?api=1&query={}\n",
+            coords(plane_pos)
+        ));
+        if let Some(observer) = observer {
+            out.push_str(&format!(
+                "Directions: https://www.google.com/maps/dir/?api=1&origin={}&destination={}\n",
+                coords(observer),
+                coords(plane_pos)
+            ));
+        }
+    }
+    if opts.osm_url {
+        let (lat, lon) = (
+            format!("{:.*}", precision, plane_pos.lat),
+            format!("{:.*}", precision, plane_pos.lon),
+        );
+        out.push_str(&format!(
+            "OpenStreetMap: https://www.openstreetmap.org/?mlat={}&mlon={}#map={}/{}/{}\n",
+            lat, lon, OSM_ZOOM, lat, lon
+        ));
+    }
+    out
+}
+
+// the --count-by-altitude-band breakdown, as bars like the histogram's
+pub(crate) fn render_altitude_bands(opts: &Options, counts: &[usize; 5]) -> String {
+    let [low, high] = opts
+        .altitude_bands
+        .unwrap_or_else(|| opts.units.default_altitude_bands());
+    let unit = opts.units.altitude_label();
+    let labels = [
+        "surface".to_string(),
+        format!("<{} {}", low, unit),
+        format!("{}-{} {}", low, high, unit),
+        format!(">{} {}", high, unit),
+        "unknown".to_string(),
+    ];
+    render_bars(&labels, counts)
+}
+
+fn render_bars(labels: &[String], counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+
+    labels
+        .iter()
+        .zip(counts)
+        .map(|(label, &count)| {
+            // round up so that any non-empty band gets at least one mark
+            let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(max));
+            format!(
+                "{:>width$} | {} {}\n",
+                label,
+                bar,
+                count,
+                width = label_width
+            )
+        })
+        .collect()
+}
+
+#[cfg(feature = "formats")]
+// quote a CSV field only when it contains a separator, quote or line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(feature = "formats")]
+// escape a Prometheus label value
+fn metric_label_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(feature = "formats")]
+fn optional_field<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| csv_field(&v.to_string())).unwrap_or_default()
+}
+
+// results go to stdout unless an output file was requested, which is created if needed.
+// A replaced file is written beside its destination and renamed over it, so a reader sees
+// either the previous result or the new one and never a mix; appends and FIFOs are
+// written in place.
+pub(crate) fn write_output(path: Option<&str>, append: bool, rendered: &str) -> io::Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None => return io::stdout().write_all(rendered.as_bytes()),
+    };
+
+    if append || is_fifo(path) {
+        return OpenOptions::new()
+            .create(true)
+            .append(append)
+            .write(true)
+            .open(path)?
+            .write_all(rendered.as_bytes());
+    }
+
+    let temp_path = format!("{}.{}.tmp", path, process::id());
+    let written = fs::write(&temp_path, rendered).and_then(|_| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &str) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::NumberLocale;
+    use crate::options::Units;
+    use crate::test_util::{sighting, synthetic_state};
+    use crate::timestamps::TimeZoneChoice;
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_write_json_output_to_file() {
+        let path =
+            std::env::temp_dir().join(format!("nearest_airplane_test_{}.json", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        let state = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            origin_country: "Ireland".to_string(),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+
+        let opts = Options {
+            format: OutputFormat::Json,
+            ..Options::default()
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        write_output(Some(path_str), false, &rendered).unwrap();
+        // a second run without --append replaces the file
+        write_output(Some(path_str), false, &rendered).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(json["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(json["icao24"], "4ca123");
+        assert_eq!(json["origin_country"], "Ireland");
+        assert_eq!(json["distance_km"], 12.5);
+        assert!(json.get("waypoints").is_none());
+
+        write_output(Some(path_str), true, &rendered).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_render_metrics() {
+        let state = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            origin_country: "Ireland".to_string(),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let opts = Options {
+            format: OutputFormat::Metrics,
+            ..Options::default()
+        };
+
+        let rendered = render_result(&opts, &[sighting(&state, 12.3)], 42);
+        let samples: Vec<&str> = rendered.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            [
+                "nearest_plane_distance_km{icao24=\"4ca123\",callsign=\"EIN123\",origin_country=\"Ireland\"} 12.3",
+                "nearest_plane_visible_planes 42",
+            ]
+        );
+        assert!(rendered.contains("# TYPE nearest_plane_distance_km gauge\n"));
+        assert!(rendered.contains("# TYPE nearest_plane_visible_planes gauge\n"));
+
+        assert_eq!(metric_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        let empty = render_result(&opts, &[], 0);
+        assert!(empty.ends_with("nearest_plane_visible_planes 0\n"));
+    }
+
+    #[test]
+    fn test_render_comparison() {
+        let state = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let observers = [
+            (
+                Some("Home"),
+                Point {
+                    lat: 53.2,
+                    lon: -6.27,
+                },
+            ),
+            (
+                None,
+                Point {
+                    lat: 53.42,
+                    lon: -6.0,
+                },
+            ),
+        ];
+        let opts = Options {
+            coord_precision: Some(2),
+            ..Options::default()
+        };
+
+        let rendered = render_comparison(&opts, &observers, &[Some((24.5, &state)), None]);
+        assert_eq!(
+            rendered,
+            "Plane 4ca123 (EIN123):\n\
+             Home (53.20, -6.27): 24.5 km, bearing 0°\n\
+             Observer 2 (53.42, -6.00): not visible\n"
+        );
+    }
+
+    #[test]
+    fn test_altitude_symbols() {
+        let bands = Units::Km.default_altitude_bands();
+        let cases = [
+            (Some(150.0), 'L'),
+            (Some(2999.9), 'L'),
+            (Some(3000.0), 'M'),
+            (Some(6500.0), 'M'),
+            (Some(9000.0), 'H'),
+            (Some(11_500.0), 'H'),
+            (None, '*'),
+        ];
+        for &(altitude, symbol) in &cases {
+            assert_eq!(altitude_symbol(altitude, bands), symbol, "{:?}", altitude);
+        }
+        assert_eq!(altitude_symbol(Some(4000.0), [5000.0, 20000.0]), 'L');
+    }
+
+    #[test]
+    fn test_render_radar() {
+        let observer = Point {
+            lat: 53.0,
+            lon: -6.0,
+        };
+        let north = OpenskyState {
+            baro_altitude: Some(11_000.0),
+            ..synthetic_state("north", 53.5, -6.0)
+        };
+        let south = OpenskyState {
+            baro_altitude: Some(500.0),
+            ..synthetic_state("south", 52.75, -6.0)
+        };
+        let unknown = synthetic_state("east", 53.0, -5.9);
+        let results = [(6.7, &unknown), (27.8, &south), (55.6, &north)];
+
+        let rendered = render_radar(&Options::default(), &observer, &results);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 2 * RADAR_HALF_HEIGHT + 2);
+        // the furthest plane sits on the ring at the top, the others inside it
+        let cell = |row: usize, col: usize| lines[row].chars().nth(col);
+        assert_eq!(cell(0, 20), Some('H'));
+        assert_eq!(cell(0, 19), Some('.'));
+        assert_eq!(cell(15, 20), Some('L'));
+        assert_eq!(cell(10, 20), Some('+'));
+        assert_eq!(cell(10, 22), Some('*'));
+        assert_eq!(cell(10, 0), Some('.'));
+        assert!(lines[21].starts_with("+ you, edge 55.6 km; L below 3000 m, M below 9000 m"));
+    }
+
+    #[test]
+    fn test_clock_positions() {
+        let cases = [
+            (0.0, 0.0, 12),
+            (0.0, 60.0, 2),
+            (0.0, 14.9, 12),
+            (0.0, 15.0, 1),
+            (90.0, 90.0, 12),
+            (90.0, 180.0, 3),
+            (90.0, 0.0, 9),
+            (270.0, 90.0, 6),
+            // wrapping past north either way
+            (350.0, 20.0, 1),
+            (10.0, 340.0, 11),
+            (330.0, 300.0, 11),
+            (359.0, 1.0, 12),
+        ];
+        for &(heading, bearing, clock) in &cases {
+            assert_eq!(
+                clock_hour(heading, bearing),
+                clock,
+                "{} {}",
+                heading,
+                bearing
+            );
+        }
+
+        let state = synthetic_state("4ca123", 53.42, -6.27);
+        let opts = Options {
+            my_heading: Some(300.0),
+            ..Options::default()
+        };
+        let abeam = Sighting {
+            bearing: Some(30.0),
+            ..sighting(&state, 12.5)
+        };
+        let rendered = render_result(&opts, &[abeam], 1);
+        assert!(rendered.contains("\nTraffic at your 3 o'clock.\n"));
+    }
+
+    #[test]
+    fn test_arrow_glyphs() {
+        let cases = [
+            (0.0, '↑', '^'),
+            (22.4, '↑', '^'),
+            (22.5, '↗', '/'),
+            (45.0, '↗', '/'),
+            (90.0, '→', '>'),
+            (135.0, '↘', '\\'),
+            (180.0, '↓', 'v'),
+            (225.0, '↙', '/'),
+            (270.0, '←', '<'),
+            (274.59, '←', '<'),
+            (315.0, '↖', '\\'),
+            (350.0, '↑', '^'),
+            (360.0, '↑', '^'),
+            (-90.0, '←', '<'),
+        ];
+        for &(degrees, unicode, ascii) in &cases {
+            assert_eq!(arrow(degrees, true), unicode, "{}", degrees);
+            assert_eq!(arrow(degrees, false), ascii, "{}", degrees);
+        }
+    }
+
+    #[test]
+    fn test_render_validation() {
+        let clean = Validation {
+            parsed: 4969,
+            skipped: 0,
+            surprises: vec![],
+        };
+        assert_eq!(
+            render_validation(&clean),
+            "Parsed 4969 plane states, skipped 0.\nNo schema surprises.\n"
+        );
+
+        let drifted = Validation {
+            parsed: 0,
+            skipped: 25,
+            surprises: (0..25)
+                .map(|i| format!("state {}: not an array", i))
+                .collect(),
+        };
+        let rendered = render_validation(&drifted);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[1], "Schema surprises: 25");
+        assert_eq!(lines[2], "  state 0: not an array");
+        assert_eq!(lines[22], "  ...and 5 more");
+        assert_eq!(lines.len(), 23);
+    }
+
+    #[test]
+    fn test_render_table() {
+        let near = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            origin_country: "Ireland".to_string(),
+            baro_altitude: Some(3048.0),
+            velocity: Some(120.0),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let far = OpenskyState {
+            origin_country: "United Kingdom".to_string(),
+            ..synthetic_state("400abc", 53.9, -5.0)
+        };
+        let sightings = [
+            Sighting {
+                bearing: Some(45.0),
+                ..sighting(&near, 8.25)
+            },
+            Sighting {
+                bearing: Some(301.6),
+                ..sighting(&far, 112.0)
+            },
+        ];
+        let opts = Options {
+            table: true,
+            ..Options::default()
+        };
+
+        let rendered = render_table(&opts, &sightings, true);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "+---------------+---------------+---------+--------------+--------------+----------------+",
+                "| Callsign      | Distance (km) | Bearing | Altitude (m) | Speed (km/h) | Country        |",
+                "+---------------+---------------+---------+--------------+--------------+----------------+",
+                "| EIN123        |           8.2 |     45° |         3048 |          432 | Ireland        |",
+                "| (no callsign) |         112.0 |    302° |            - |            - | United Kingdom |",
+                "+---------------+---------------+---------+--------------+--------------+----------------+",
+            ]
+        );
+
+        let rendered = render_table(&opts, &sightings, false);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "Callsign       Distance (km)  Bearing  Altitude (m)  Speed (km/h)  Country"
+        );
+        assert_eq!(
+            lines[1],
+            "EIN123                   8.2      45°          3048           432  Ireland"
+        );
+
+        // watching adds a status column, and highlights the changes on a terminal
+        let watching = [
+            Sighting {
+                watch_status: Some(WatchStatus::New),
+                ..sighting(&near, 8.25)
+            },
+            Sighting {
+                watch_status: Some(WatchStatus::Departed),
+                ..sighting(&far, 112.0)
+            },
+        ];
+        let opts = Options {
+            watch: Some(10),
+            ..opts
+        };
+        let rendered = render_table(&opts, &watching, false);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert!(lines[0].ends_with("  Country         Status"));
+        assert!(lines[1].ends_with("  Ireland         new"));
+        assert!(lines[2].ends_with("  United Kingdom  departed"));
+
+        let rendered = render_table(&opts, &watching, true);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert!(
+            lines[3].starts_with("\x1b[1m| EIN123 ") && lines[3].ends_with("| new      |\x1b[0m")
+        );
+        assert!(lines[4].starts_with("\x1b[2;9m| (no callsign) "));
+        assert!(lines[4].ends_with("| departed |\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_locale() {
+        let state = OpenskyState {
+            baro_altitude: Some(3048.0),
+            velocity: Some(350.0),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let opts = Options {
+            locale: Some(NumberLocale::from_name("de_DE.UTF-8").unwrap()),
+            ..Options::default()
+        };
+
+        let rendered = render_result(&opts, &[sighting(&state, 2887.26)], 1);
+        assert!(rendered.starts_with("Result: "));
+        assert!(rendered.contains(" with distance 2.887,3 km.\n"));
+        assert!(rendered.contains("\nSpeed: 1.260 km/h\n"));
+        // positions are coordinates, not quantities, so they keep the '.'
+        assert!(rendered.contains("\nPlane position: 53.4200, -6.2700\n"));
+
+        let rendered = render_table(&opts, &[sighting(&state, 2887.26)], false);
+        assert!(rendered.lines().nth(1).unwrap().contains("2.887,3  "));
+
+        #[cfg(feature = "formats")]
+        {
+            // machine-readable output is for parsing, so it never follows the locale
+            let opts = Options {
+                format: OutputFormat::Csv,
+                ..opts
+            };
+            let rendered = render_result(&opts, &[sighting(&state, 2887.26)], 1);
+            assert!(rendered.lines().nth(1).unwrap().ends_with(",2887.3"));
+        }
+    }
+
+    #[test]
+    fn test_callsign_label() {
+        let named = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let unnamed = synthetic_state("4ca124", 53.42, -6.27);
+        let default = Options::default();
+        assert_eq!(callsign_label(&default, &named), "EIN123");
+        assert_eq!(callsign_label(&default, &unnamed), "(no callsign)");
+
+        let opts = Options {
+            no_callsign_label: Some("-".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(callsign_label(&opts, &unnamed), "-");
+        let opts = Options {
+            callsign_fallback_icao24: true,
+            ..opts
+        };
+        assert_eq!(callsign_label(&opts, &named), "EIN123");
+        let rendered = render_result(&opts, &[sighting(&unnamed, 1.0)], 1);
+        assert!(rendered.contains("\nCallsign: 4ca124\n"));
+    }
+
+    #[test]
+    fn test_render_human() {
+        let state = OpenskyState {
+            time_position: Some(1595268619),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let opts = Options {
+            tz: TimeZoneChoice::Utc,
+            ..Options::default()
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert!(lines[0].starts_with("Result: OpenskyState { icao24: \"4ca123\""));
+        assert!(lines[0].ends_with(" with distance 12.5 km."));
+        assert_eq!(lines[1], "Callsign: (no callsign)");
+        assert_eq!(lines[2], "Plane position: 53.4200, -6.2700");
+        assert!(lines[3].starts_with("Position time: 2020-07-20 18:10:19 UTC"));
+        assert_eq!(
+            lines[5],
+            "Projection unavailable: missing track and velocity."
+        );
+        assert_eq!(lines.len(), 6);
+
+        let moving = OpenskyState {
+            velocity: Some(230.0),
+            ..state.clone()
+        };
+        let rendered = render_result(&opts, &[sighting(&moving, 12.5)], 1);
+        assert!(rendered.contains("\nSpeed: 828 km/h\n"));
+
+        let state = OpenskyState {
+            geo_altitude: Some(1000.0),
+            baro_altitude: Some(1030.48),
+            ..state
+        };
+        let opts = Options {
+            show_alt_delta: true,
+            units: Units::Mi,
+            ..opts
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        assert!(rendered.contains("\nAltitude delta (geo - baro): -100 ft\n"));
+
+        let opts = Options {
+            coord_precision: Some(1),
+            ..opts
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        assert!(rendered.contains("\nPlane position: 53.4, -6.3\n"));
+
+        let labeled = Sighting {
+            observer_label: Some("Home"),
+            ..sighting(&state, 12.5)
+        };
+        let rendered = render_result(&opts, &[labeled], 1);
+        assert!(rendered.starts_with("Observer: Home\nResult: "));
+        let heading_ne = Sighting {
+            bearing: Some(40.0),
+            ..sighting(&state, 12.5)
+        };
+        let opts = Options {
+            arrow: Some(ArrowSource::Bearing),
+            ..opts
+        };
+        let rendered = render_result(&opts, &[heading_ne], 1);
+        assert!(rendered.contains("\nDirection: ↗ 40° (bearing)\n"));
+
+        let state = OpenskyState {
+            true_track: Some(181.0),
+            ..state
+        };
+        let opts = Options {
+            arrow: Some(ArrowSource::Track),
+            no_unicode: true,
+            ..opts
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        assert!(rendered.contains("\nDirection: v 181° (track)\n"));
+    }
+
+    #[test]
+    fn test_output_replaced_each_tick() {
+        let dir = std::env::temp_dir().join(format!("nearest_airplane_ticks_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("latest.txt");
+        let path_str = path.to_str().unwrap();
+
+        for tick in 1..=3 {
+            let rendered = format!("tick {}\n", tick).repeat(tick);
+            write_output(Some(path_str), false, &rendered).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), rendered);
+            // nothing but the result itself is left behind
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        }
+
+        write_output(Some(path_str), true, "tick 4\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "tick 3\ntick 3\ntick 3\ntick 4\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_distance() {
+        let with = |distance_precision, distance_sig_figs| Options {
+            distance_precision,
+            distance_sig_figs,
+            ..Options::default()
+        };
+        let distance = 2887.2599506071106;
+
+        assert_eq!(format_distance(&Options::default(), distance), "2887.3");
+        assert_eq!(format_distance(&with(Some(0), None), distance), "2887");
+        assert_eq!(format_distance(&with(Some(3), None), distance), "2887.260");
+        assert_eq!(format_distance(&with(None, Some(2)), distance), "2900");
+        assert_eq!(format_distance(&with(None, Some(6)), distance), "2887.26");
+        assert_eq!(format_distance(&with(None, Some(2)), 0.0123456), "0.012");
+        assert_eq!(format_distance(&with(None, Some(3)), 0.0), "0");
+    }
+
+    #[test]
+    fn test_render_nearest_place() {
+        let place = Place {
+            name: "Dublin Airport".to_string(),
+            position: Point {
+                lat: 53.4213,
+                lon: -6.2701,
+            },
+        };
+        assert_eq!(
+            render_nearest_place(&Options::default(), &place, 2887.2599506071106),
+            "Nearest place: Dublin Airport with distance 2887.3 km.\n"
+        );
+
+        let opts = Options {
+            distance_precision: Some(2),
+            locale: Some(NumberLocale::from_name("de_DE").unwrap()),
+            ..Options::default()
+        };
+        assert_eq!(
+            render_nearest_place(&opts, &place, 2887.2599506071106),
+            "Nearest place: Dublin Airport with distance 2.887,26 km.\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_json_keeps_full_precision() {
+        let state = synthetic_state("4ca123", 53.42, -6.27);
+        let opts = Options {
+            format: OutputFormat::Json,
+            distance_precision: Some(0),
+            ..Options::default()
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 2887.2599506071106)], 1);
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["distance_km"], 2887.2599506071106);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let distances = [0.5, 3.0, 9.99, 10.0, 14.2, 31.7, 38.0, 39.9];
+        let counts = histogram(&distances, 10.0);
+        assert_eq!(counts, vec![3, 2, 0, 3]);
+        assert_eq!(histogram(&distances, 20.0), vec![5, 3]);
+        assert!(histogram(&[], 10.0).is_empty());
+
+        let rendered = render_histogram(&Options::default(), &counts, 10.0);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!(" 0-10 km | {} 3", "#".repeat(50)));
+        assert_eq!(lines[2], "20-30 km |  0");
+
+        let rendered = render_histogram(&Options::default(), &[1, 2, 0, 4], 0.1);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], format!("0.0-0.1 km | {} 1", "#".repeat(13)));
+        assert_eq!(lines[3], format!("0.3-0.4 km | {} 4", "#".repeat(50)));
+    }
+
+    #[test]
+    fn test_altitude_band_counts() {
+        let at = |icao24, ft: Option<f64>| OpenskyState {
+            baro_altitude: ft.map(|ft| ft * 0.3048),
+            ..synthetic_state(icao24, 53.4, -6.2)
+        };
+        let states = [
+            OpenskyState {
+                on_ground: true,
+                ..at("a", Some(0.0))
+            },
+            at("b", Some(2000.0)),
+            at("c", Some(9000.0)),
+            at("d", Some(15000.0)),
+            at("e", Some(29000.0)),
+            at("f", Some(38000.0)),
+            at("g", None),
+        ];
+        let results: Vec<(f64, &OpenskyState)> = states.iter().map(|s| (1.0, s)).collect();
+        let opts = Options {
+            units: Units::Mi,
+            ..Options::default()
+        };
+        let counts = altitude_band_counts(&opts, &results);
+        assert_eq!(counts, [1, 2, 2, 1, 1]);
+
+        let rendered = render_altitude_bands(&opts, &counts);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], format!("       surface | {} 1", "#".repeat(25)));
+        assert_eq!(lines[2], format!("10000-30000 ft | {} 2", "#".repeat(50)));
+        assert_eq!(lines[4], format!("       unknown | {} 1", "#".repeat(25)));
+
+        // the limits can move
+        let opts = Options {
+            altitude_bands: Some([5000.0, 20000.0]),
+            ..opts
+        };
+        assert_eq!(altitude_band_counts(&opts, &results), [1, 1, 2, 2, 1]);
+        assert!(render_altitude_bands(&opts, &counts).contains("\n5000-20000 ft | "));
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_render_csv() {
+        let state = OpenskyState {
+            callsign: "EIN123  ".to_string(),
+            origin_country: "Korea, Republic of".to_string(),
+            velocity: Some(120.5),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+
+        let opts = Options {
+            format: OutputFormat::Csv,
+            ..Options::default()
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 12.5)], 1);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            lines[0],
+            "icao24,callsign,origin_country,latitude,longitude,baro_altitude_m,\
+             geo_altitude_m,velocity_kmh,true_track,squawk,distance_km"
+        );
+        assert_eq!(
+            lines[1],
+            "4ca123,EIN123,\"Korea, Republic of\",53.42,-6.27,,,433.8,,,12.5"
+        );
+
+        let state = OpenskyState {
+            baro_altitude: Some(3048.0),
+            ..state
+        };
+        let args = "--format csv --units nmi --distance-precision 2".split(' ');
+        let opts = Options::from_args(args.map(String::from)).unwrap();
+        let rendered = render_result(&opts, &[sighting(&state, 18.52)], 1);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert!(lines[0].contains(",baro_altitude_ft,geo_altitude_ft,"));
+        assert!(lines[0].ends_with(",distance_nmi"));
+        assert!(lines[0].contains(",velocity_knots,"));
+        assert!(lines[1].ends_with(",53.42,-6.27,10000.00,,234.23,,,10.00"));
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_json_dual_units() {
+        let state = OpenskyState {
+            baro_altitude: Some(3048.0),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let args = vec!["--json", "--units", "nmi"];
+        let opts = Options::from_args(args.into_iter().map(String::from)).unwrap();
+
+        let rendered = render_result(&opts, &[sighting(&state, 18.52)], 1);
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["schema_version"], "1.6");
+        assert!(rendered.starts_with("{\"schema_version\":"));
+        assert_eq!(json["distance_km"], 18.52);
+        assert!((json["distance"].as_f64().unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(json["unit"], "nmi");
+        assert_eq!(json["altitude_m"], 3048.0);
+        assert!((json["altitude"].as_f64().unwrap() - 10000.0).abs() < 1e-9);
+        assert_eq!(json["altitude_unit"], "ft");
+        assert!(json["speed"].is_null());
+        assert_eq!(json["speed_unit"], "kn");
+
+        // unknown altitude stays explicit rather than disappearing
+        let state = synthetic_state("4ca124", 53.42, -6.27);
+        let rendered = render_result(&opts, &[sighting(&state, 18.52)], 1);
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert!(json["altitude_m"].is_null() && json["altitude"].is_null());
+    }
+
+    #[test]
+    fn test_mid_bearing() {
+        let observer = Point {
+            lat: 40.71,
+            lon: -74.01,
+        };
+        let state = synthetic_state("4ca123", 40.42, -3.70);
+        let sighting = Sighting::new(&Options::default(), &observer, &state, 5000.0, 0);
+        assert!(sighting.mid_bearing.is_none());
+
+        let opts = Options {
+            mid_bearing: true,
+            ..Options::default()
+        };
+        let sighting = Sighting::new(&opts, &observer, &state, 5000.0, 0);
+        let rendered = render_result(&opts, &[sighting], 1);
+        assert!(rendered.contains("\nBearing: 65.7° initially, 90.4° at mid-path\n"));
+    }
+
+    #[test]
+    fn test_map_links() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        let state = synthetic_state("4ca123", 53.42, -6.27);
+        let opts = Options {
+            maps_url: true,
+            osm_url: true,
+            ..Options::default()
+        };
+        let located = Sighting::new(&opts, &observer, &state, 24.0, 0);
+        let rendered = render_result(&opts, &[located], 1);
+        assert!(rendered.contains(
+            "\nMap: https://www.google.com/maps/search/?api=1&query=53.4200,-6.2700\n\
+             Directions: https://www.google.com/maps/dir/?api=1\
+             &origin=53.2100,-6.1800&destination=53.4200,-6.2700\n\
+             OpenStreetMap: https://www.openstreetmap.org/?mlat=53.4200&mlon=-6.2700\
+             #map=12/53.4200/-6.2700\n"
+        ));
+
+        // the links follow --coord-precision, and need asking for
+        let opts = Options {
+            coord_precision: Some(2),
+            osm_url: false,
+            ..opts
+        };
+        assert_eq!(
+            map_links(&opts, None, &state.position().unwrap()),
+            "Map: https://www.google.com/maps/search/?api=1&query=53.42,-6.27\n"
+        );
+        let rendered = render_result(&Options::default(), &[sighting(&state, 24.0)], 1);
+        assert!(!rendered.contains("https://"));
+    }
+
+    #[test]
+    #[cfg(feature = "formats")]
+    fn test_raw_index() {
+        let state = OpenskyState {
+            response_index: Some(7),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let opts = Options {
+            format: OutputFormat::Json,
+            ..Options::default()
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 1.0)], 1);
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert!(json.get("response_index").is_none());
+
+        let opts = Options {
+            raw_index: true,
+            ..opts
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 1.0)], 1);
+        let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json["response_index"], 7);
+
+        let opts = Options {
+            format: OutputFormat::Human,
+            ..opts
+        };
+        let rendered = render_result(&opts, &[sighting(&state, 1.0)], 1);
+        assert!(rendered.contains("\nResponse index: 7\n"));
+    }
+
+    #[test]
+    fn test_contrail_badge() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        let at = |m| OpenskyState {
+            baro_altitude: Some(m),
+            ..synthetic_state("4ca123", 53.42, -6.27)
+        };
+        let badged = |opts: &Options, m| {
+            let state = at(m);
+            let sighting = Sighting::new(opts, &observer, &state, 24.0, 0);
+            render_result(opts, &[sighting], 1).contains("\n[CONTRAIL-LIKELY] ")
+        };
+
+        let opts = Options {
+            contrails: true,
+            ..Options::default()
+        };
+        assert!(badged(&opts, 10000.0));
+        assert!(badged(&opts, 8000.0));
+        assert!(!badged(&opts, 7999.0));
+        assert!(!badged(&Options::default(), 10000.0));
+
+        // the threshold follows the altitude units
+        let opts = Options {
+            units: Units::Mi,
+            contrail_altitude: Some(35000.0),
+            ..opts
+        };
+        assert!(!badged(&opts, 10000.0));
+        assert!(badged(&opts, 11000.0));
+    }
+
+    #[test]
+    fn test_cross_track_overhead() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+
+        // due north of the observer, heading straight south over it
+        let plane = synthetic_state("overhead", 53.71, -6.18);
+        let state = OpenskyState {
+            true_track: Some(180.0),
+            ..plane
+        };
+        let flyover = Sighting::new(&Options::default(), &observer, &state, 55.6, 0)
+            .flyover
+            .unwrap();
+        assert!(flyover.cross_track_km < 1e-6);
+        assert!(flyover.approaching);
+
+        // the same course seen from 0.5° further east passes about 33 km away
+        let east = Point {
+            lat: 53.21,
+            lon: -5.68,
+        };
+        let flyover = Sighting::new(&Options::default(), &east, &state, 60.0, 0)
+            .flyover
+            .unwrap();
+        assert!((flyover.cross_track_km - 33.3).abs() < 0.5);
+
+        // heading north, away from the observer
+        let state = OpenskyState {
+            true_track: Some(0.0),
+            ..state
+        };
+        let flyover = Sighting::new(&Options::default(), &observer, &state, 55.6, 0)
+            .flyover
+            .unwrap();
+        assert!(!flyover.approaching);
+
+        // no track, no flyover
+        let state = OpenskyState {
+            true_track: None,
+            ..state
+        };
+        assert!(
+            Sighting::new(&Options::default(), &observer, &state, 55.6, 0)
+                .flyover
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_projection_fallback() {
+        let observer = Point {
+            lat: 53.21,
+            lon: -6.18,
+        };
+        let opts = Options {
+            tz: TimeZoneChoice::Utc,
+            ..Options::default()
+        };
+
+        // heading straight for the observer from 55.6 km north at 200 m/s
+        let state = OpenskyState {
+            true_track: Some(180.0),
+            velocity: Some(200.0),
+            ..synthetic_state("4ca123", 53.71, -6.18)
+        };
+        let full = Sighting::new(&opts, &observer, &state, 55.6, 0);
+        let secs = full.flyover.unwrap().closest_in_secs.unwrap();
+        assert!((secs - 278.0).abs() < 1.0, "{}", secs);
+        let rendered = render_result(&opts, &[full], 1);
+        assert!(rendered.contains("\nWill pass ~0.0 km to your side in about 5 min.\n"));
+        assert!(!rendered.contains("Projection unavailable"));
+
+        // velocity but no track: everything else still renders
+        let no_track = OpenskyState {
+            true_track: None,
+            ..state.clone()
+        };
+        let rendered = render_result(
+            &opts,
+            &[Sighting::new(&opts, &observer, &no_track, 55.6, 0)],
+            1,
+        );
+        assert!(rendered.starts_with("Result: "));
+        assert!(rendered.contains("\nPlane position: 53.7100, -6.1800\n"));
+        assert!(rendered.contains("\nSpeed: 720 km/h\n"));
+        assert!(rendered.contains("\nLast contact: "));
+        assert!(!rendered.contains("Will pass"));
+        assert!(rendered.ends_with("\nProjection unavailable: missing track.\n"));
+
+        // a track but no velocity still says where it'll pass, just not when
+        let no_velocity = OpenskyState {
+            velocity: None,
+            ..state
+        };
+        let rendered = render_result(
+            &opts,
+            &[Sighting::new(&opts, &observer, &no_velocity, 55.6, 0)],
+            1,
+        );
+        assert!(rendered.contains("\nWill pass ~0.0 km to your side.\n"));
+        assert!(rendered.ends_with("\nProjection unavailable: missing velocity.\n"));
+    }
+}
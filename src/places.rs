@@ -0,0 +1,108 @@
+use crate::coords::Point;
+use crate::options::Options;
+
+// a named reference point, such as an airport or a city
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Place {
+    pub(crate) name: String,
+    pub(crate) position: Point,
+}
+
+// one "name,lat,lon" per line; blank lines and lines starting with # are skipped, and the
+// name may itself contain commas since the coordinates are taken from the end
+pub(crate) fn parse_places(text: &str) -> Result<Vec<Place>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_no, line)| {
+            let mut fields = line.rsplitn(3, ',');
+            let (lon, lat, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(lon), Some(lat), Some(name)) => (lon, lat, name.trim()),
+                _ => return Err(format!("line {}: expected name,lat,lon", line_no)),
+            };
+            let parse = |value: &str| {
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("line {}: invalid coordinate: {}", line_no, value))
+            };
+            let position = Point::checked(parse(lat)?, parse(lon)?)
+                .map_err(|e| format!("line {}: {}", line_no, e))?;
+
+            Ok(Place {
+                name: name.to_string(),
+                position,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn nearest_place<'a>(
+    observer: &Point,
+    places: &'a [Place],
+    opts: &Options,
+) -> Option<(f64, &'a Place)> {
+    places
+        .iter()
+        .map(|place| (opts.distance(observer, place.position), place))
+        .min_by(|(d1, _), (d2, _)| d1.partial_cmp(d2).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIRPORTS: &str = "\
+# name,lat,lon
+Dublin (DUB),53.4213,-6.2701
+
+Belfast International (BFS),54.6575,-6.2158
+Shannon (SNN),52.7020,-8.9248
+Cork (ORK),51.8413,-8.4911
+";
+
+    #[test]
+    fn test_nearest_place() {
+        let places = parse_places(AIRPORTS).unwrap();
+        assert_eq!(places.len(), 4);
+        let opts = Options::default();
+
+        // Greystones, Co. Wicklow
+        let observer = Point {
+            lat: 53.14,
+            lon: -6.06,
+        };
+        let (distance, place) = nearest_place(&observer, &places, &opts).unwrap();
+        assert_eq!(place.name, "Dublin (DUB)");
+        assert!((distance - 34.3).abs() < 0.1);
+
+        // Limerick
+        let observer = Point {
+            lat: 52.66,
+            lon: -8.63,
+        };
+        let (_, place) = nearest_place(&observer, &places, &opts).unwrap();
+        assert_eq!(place.name, "Shannon (SNN)");
+
+        assert!(nearest_place(&observer, &[], &opts).is_none());
+    }
+
+    #[test]
+    fn test_parse_places() {
+        let places = parse_places("Washington, D.C., 38.9, -77.04").unwrap();
+        assert_eq!(places[0].name, "Washington, D.C.");
+        assert_eq!(
+            places[0].position,
+            Point {
+                lat: 38.9,
+                lon: -77.04
+            }
+        );
+
+        assert!(parse_places("Dublin,53.42").is_err());
+        assert!(parse_places("Dublin,north,-6.27").is_err());
+        let err = parse_places("ok,1,2\nNowhere,91,0").unwrap_err();
+        assert!(err.starts_with("line 2: "), "{}", err);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::coords::Point;
+
+// Open Location Code ("plus code") digits, each worth its index
+const ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+// where the '+' sits in a full code
+const SEPARATOR_POSITION: usize = 8;
+// digits after the first ten refine a 5 by 4 grid instead of coming in pairs
+const PAIR_DIGITS: usize = 10;
+const MAX_DIGITS: usize = 15;
+
+// the center of the area a full plus code like "9C5M8QJ8+2V" covers. Short codes such as
+// "8QJ8+2V" are relative to somewhere nearby, so they're rejected rather than guessed at.
+pub(crate) fn decode_plus_code(code: &str) -> Result<Point, String> {
+    let invalid = |reason: &str| format!("Invalid plus code {}: {}", code, reason);
+    let upper = code.trim().to_ascii_uppercase();
+
+    let (before, after) = upper
+        .split_once('+')
+        .ok_or_else(|| invalid("missing the '+'"))?;
+    if after.contains('+') {
+        return Err(invalid("more than one '+'"));
+    }
+    if before.len() < SEPARATOR_POSITION {
+        return Err(invalid(
+            "short codes need a reference location; use the full code",
+        ));
+    }
+    if before.len() > SEPARATOR_POSITION || after.len() == 1 {
+        return Err(invalid("the '+' is in the wrong place"));
+    }
+
+    // padding fills out a code for a larger area, and nothing can follow it
+    let digits = before.trim_end_matches('0');
+    if digits.len() % 2 == 1
+        || digits.is_empty()
+        || (digits.len() < before.len() && !after.is_empty())
+    {
+        return Err(invalid("badly placed padding"));
+    }
+    let digits: Vec<u8> = digits
+        .bytes()
+        .chain(after.bytes())
+        .take(MAX_DIGITS)
+        .collect();
+    let values = digits
+        .iter()
+        .map(|&c| ALPHABET.iter().position(|&a| a == c).map(|v| v as f64))
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| invalid("unexpected character"))?;
+    if values[0] >= 9.0 || values[1] >= 18.0 {
+        return Err(invalid("out of range"));
+    }
+
+    let (mut lat, mut lon) = (-90.0, -180.0);
+    let (mut lat_size, mut lon_size) = (400.0, 400.0);
+    for pair in values[..values.len().min(PAIR_DIGITS)].chunks(2) {
+        lat_size /= 20.0;
+        lon_size /= 20.0;
+        lat += pair[0] * lat_size;
+        lon += pair[1] * lon_size;
+    }
+    for &value in values.iter().skip(PAIR_DIGITS) {
+        lat_size /= 5.0;
+        lon_size /= 4.0;
+        lat += (value / 4.0).floor() * lat_size;
+        lon += (value % 4.0) * lon_size;
+    }
+
+    Point::checked((lat + lat_size / 2.0).min(90.0), lon + lon_size / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_plus_code() {
+        let cases = [
+            // the Googleplex, within the code's 14 m square
+            ("849VCWC8+R9", 37.4220625, -122.0840625, 1.25e-4),
+            ("849vcwc8+r9", 37.4220625, -122.0840625, 1.25e-4),
+            // an extra grid digit narrows it down further
+            ("8FVC9G8F+6XQ", 47.3655875, 8.524984375, 2.5e-5),
+            // padded codes cover a whole block
+            ("8FVC0000+", 47.5, 8.5, 1.0),
+        ];
+        for &(code, lat, lon, size) in &cases {
+            let p = decode_plus_code(code).unwrap();
+            assert!(
+                (p.lat - lat).abs() < size / 2.0 && (p.lon - lon).abs() < size / 2.0,
+                "{} decoded to {}",
+                code,
+                p
+            );
+        }
+
+        for code in &[
+            "CWC8+R9",
+            "849VCWC8R9",
+            "849VCWC8+R",
+            "849VCWC8+R9+",
+            "849VCW00+R9",
+            "849VCWC8+RA",
+            "X49VCWC8+R9",
+            "+",
+        ] {
+            assert!(decode_plus_code(code).is_err(), "{} decoded", code);
+        }
+        assert_eq!(
+            decode_plus_code("CWC8+R9").unwrap_err(),
+            "Invalid plus code CWC8+R9: short codes need a reference location; use the full code"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::Error;
+use crate::opensky::{BoundingBox, OpenskyState, PlaneSource};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::net::TcpStream;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// dump1090 and friends serve BaseStation messages here
+pub(crate) const DEFAULT_SBS_PORT: u16 = 30003;
+pub(crate) const DEFAULT_SBS_WINDOW_SECS: u64 = 5;
+
+const M_PER_FT: f64 = 0.3048;
+const MS_PER_KNOT: f64 = 1852.0 / 3600.0;
+
+// positions of the fields in a "MSG,..." line
+const HEX_IDENT: usize = 4;
+const CALLSIGN: usize = 10;
+const ALTITUDE: usize = 11;
+const GROUND_SPEED: usize = 12;
+const TRACK: usize = 13;
+const LATITUDE: usize = 14;
+const LONGITUDE: usize = 15;
+const VERTICAL_RATE: usize = 16;
+const SQUAWK: usize = 17;
+const SPI: usize = 20;
+const ON_GROUND: usize = 21;
+
+// a local receiver's SBS (BaseStation) feed, listened to for a short window each fetch
+pub struct SbsSource {
+    // host:port, e.g. localhost:30003
+    pub addr: String,
+    pub window: Duration,
+}
+
+impl PlaneSource for SbsSource {
+    fn fetch(&self, bbox: Option<&BoundingBox>) -> Result<Vec<OpenskyState>, Error> {
+        let network = |e: std::io::Error| Error::Network(format!("{}: {}", self.addr, e));
+        let stream = TcpStream::connect(&self.addr).map_err(network)?;
+        let started = Instant::now();
+
+        let mut reader = BufReader::new(stream);
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        while let Some(remaining) = self.window.checked_sub(started.elapsed()) {
+            if remaining.is_zero() {
+                break;
+            }
+            reader
+                .get_ref()
+                .set_read_timeout(Some(remaining))
+                .map_err(network)?;
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => lines.push(line.clone()),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => return Err(network(e)),
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as usize)
+            .unwrap_or(0);
+        let states = assemble_states(lines.iter().map(String::as_str), now);
+        Ok(match bbox {
+            Some(bbox) => states
+                .into_iter()
+                .filter(|s| s.position().is_some_and(|p| bbox.contains(&p)))
+                .collect(),
+            None => states,
+        })
+    }
+}
+
+// merge the messages about each aircraft into one state, as each SBS message only carries
+// some of the fields: callsigns come in one, positions in another, speeds in a third
+pub(crate) fn assemble_states<'a, I: IntoIterator<Item = &'a str>>(
+    lines: I,
+    now: usize,
+) -> Vec<OpenskyState> {
+    let mut states: BTreeMap<String, OpenskyState> = BTreeMap::new();
+
+    for line in lines {
+        let fields: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+        if fields.first() != Some(&"MSG") || fields.len() <= CALLSIGN {
+            continue;
+        }
+        let icao24 = fields[HEX_IDENT].to_ascii_lowercase();
+        if icao24.is_empty() {
+            continue;
+        }
+        let field = |i: usize| fields.get(i).copied().filter(|f| !f.is_empty());
+        let number = |i: usize| field(i).and_then(|f| f.parse::<f64>().ok());
+
+        let state = states
+            .entry(icao24.clone())
+            .or_insert_with(|| new_state(icao24, now));
+        state.last_contact = now;
+        if let Some(callsign) = field(CALLSIGN) {
+            state.callsign = callsign.to_string();
+        }
+        if let Some(ft) = number(ALTITUDE) {
+            state.baro_altitude = Some(ft * M_PER_FT);
+        }
+        if let Some(knots) = number(GROUND_SPEED) {
+            state.velocity = Some(knots * MS_PER_KNOT);
+        }
+        if let Some(track) = number(TRACK) {
+            state.true_track = Some(track);
+        }
+        if let (Some(lat), Some(lon)) = (number(LATITUDE), number(LONGITUDE)) {
+            state.latitude = Some(lat);
+            state.longitude = Some(lon);
+            state.time_position = Some(now);
+        }
+        if let Some(ft_per_min) = number(VERTICAL_RATE) {
+            state.vertical_rate = Some(ft_per_min * M_PER_FT / 60.0);
+        }
+        if let Some(squawk) = field(SQUAWK) {
+            state.squawk = Some(squawk.to_string());
+        }
+        // flags are -1 for set and 0 for clear
+        if let Some(spi) = field(SPI) {
+            state.spi = spi == "-1";
+        }
+        if let Some(on_ground) = field(ON_GROUND) {
+            state.on_ground = on_ground == "-1";
+        }
+    }
+
+    states.into_values().collect()
+}
+
+fn new_state(icao24: String, now: usize) -> OpenskyState {
+    OpenskyState {
+        icao24,
+        callsign: String::new(),
+        origin_country: String::new(),
+        time_position: None,
+        last_contact: now,
+        longitude: None,
+        latitude: None,
+        baro_altitude: None,
+        on_ground: false,
+        velocity: None,
+        true_track: None,
+        vertical_rate: None,
+        sensors: None,
+        geo_altitude: None,
+        squawk: None,
+        spi: false,
+        // ADS-B, which is all a receiver like dump1090 decodes positions from
+        position_source: 0,
+        category: None,
+        response_index: None,
+        response_time: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_states() {
+        let lines = [
+            "MSG,1,111,11111,4CA123,111111,2020/07/20,18:10:19.000,2020/07/20,18:10:19.000,EIN123  ,,,,,,,,,,,0",
+            "MSG,3,111,11111,4CA123,111111,2020/07/20,18:10:19.100,2020/07/20,18:10:19.100,,10000,,,53.42,-6.27,,,0,0,0,0",
+            "MSG,4,111,11111,4CA123,111111,2020/07/20,18:10:19.200,2020/07/20,18:10:19.200,,,250,270.5,,,-640,,0,0,0,0",
+            "MSG,6,111,11111,4CA123,111111,2020/07/20,18:10:19.300,2020/07/20,18:10:19.300,,,,,,,,7000,0,0,-1,0",
+            // a second plane that's only been heard once, and some noise
+            "MSG,5,111,11111,A808C4,111111,2020/07/20,18:10:19.400,2020/07/20,18:10:19.400,,36000,,,,,,,0,,0,0",
+            "STA,,5,179,400AE7,10103,2008/11/28,14:58:51.153,2008/11/28,14:58:51.153,RM",
+            "MSG,3,111,11111,,111111",
+            "",
+        ];
+        let states = assemble_states(lines.iter().copied(), 1595268619);
+        assert_eq!(states.len(), 2);
+
+        let state = &states[0];
+        assert_eq!(state.icao24, "4ca123");
+        assert_eq!(state.callsign, "EIN123");
+        assert_eq!(
+            state.position().map(|p| (p.lat, p.lon)),
+            Some((53.42, -6.27))
+        );
+        assert_eq!(state.time_position, Some(1595268619));
+        assert!((state.baro_altitude.unwrap() - 3048.0).abs() < 1e-9);
+        assert!((state.velocity.unwrap() - 128.611).abs() < 1e-3);
+        assert_eq!(state.true_track, Some(270.5));
+        assert!((state.vertical_rate.unwrap() + 3.251).abs() < 1e-3);
+        assert_eq!(state.squawk.as_deref(), Some("7000"));
+        assert!(state.spi && !state.on_ground);
+
+        // without a position message there's nothing to measure distance to yet
+        assert_eq!(states[1].icao24, "a808c4");
+        assert!(states[1].position().is_none());
+        assert_eq!(states[1].time_position, None);
+    }
+}
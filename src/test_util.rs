@@ -0,0 +1,53 @@
+use crate::opensky::OpenskyState;
+use crate::output::Sighting;
+use std::{fs::File, io::Read};
+
+pub(crate) fn read_file_bytes(path: &str) -> Vec<u8> {
+    let mut f = File::open(path).unwrap_or_else(|_| panic!("Can't open sample file {}", path));
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf)
+        .unwrap_or_else(|_| panic!("Can't read sample file {}", path));
+    buf
+}
+
+pub(crate) fn sighting(state: &OpenskyState, distance: f64) -> Sighting<'_> {
+    Sighting {
+        observer_label: None,
+        observer: None,
+        state,
+        distance,
+        bearing: None,
+        mid_bearing: None,
+        flyover: None,
+        overhead: false,
+        contrail_likely: false,
+        waypoints: None,
+        now: 0,
+        watch_status: None,
+    }
+}
+
+pub(crate) fn synthetic_state(icao24: &str, lat: f64, lon: f64) -> OpenskyState {
+    OpenskyState {
+        icao24: icao24.to_string(),
+        callsign: String::new(),
+        origin_country: String::new(),
+        time_position: None,
+        last_contact: 0,
+        longitude: Some(lon),
+        latitude: Some(lat),
+        baro_altitude: None,
+        on_ground: false,
+        velocity: None,
+        true_track: None,
+        vertical_rate: None,
+        sensors: None,
+        geo_altitude: None,
+        squawk: None,
+        spi: false,
+        position_source: 0,
+        response_index: None,
+        response_time: None,
+        category: None,
+    }
+}
@@ -0,0 +1,195 @@
+// which clock face to show "time_position"/"last_contact" timestamps on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeZoneChoice {
+    // the system time zone, which needs the chrono feature to look up
+    Local,
+    Utc,
+    // a fixed offset in seconds east of UTC
+    Offset(i32),
+}
+
+impl Default for TimeZoneChoice {
+    #[cfg(feature = "chrono")]
+    fn default() -> Self {
+        TimeZoneChoice::Local
+    }
+
+    #[cfg(not(feature = "chrono"))]
+    fn default() -> Self {
+        TimeZoneChoice::Utc
+    }
+}
+
+impl TimeZoneChoice {
+    // "local", "utc" or a fixed offset like "+02:00", "-0330" or "+5"
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "local" if cfg!(feature = "chrono") => return Ok(TimeZoneChoice::Local),
+            "local" => return Err("--tz local needs the chrono feature".to_string()),
+            "utc" | "z" => return Ok(TimeZoneChoice::Utc),
+            _ => {}
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid time zone: {} (expected local, utc or +HH:MM)",
+                name
+            )
+        };
+        let (sign, rest) = match name.chars().next() {
+            Some('+') => (1, &name[1..]),
+            Some('-') => (-1, &name[1..]),
+            _ => return Err(invalid()),
+        };
+        let digits: String = rest.chars().filter(|&c| c != ':').collect();
+        let (hours, minutes) = match digits.len() {
+            1 | 2 => (digits.as_str(), "0"),
+            4 => digits.split_at(2),
+            _ => return Err(invalid()),
+        };
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 14 || minutes >= 60 {
+            return Err(invalid());
+        }
+
+        Ok(TimeZoneChoice::Offset(sign * (hours * 3600 + minutes * 60)))
+    }
+}
+
+// e.g. "2020-07-20 18:10:19 UTC (1s ago)", or "unknown" when OpenSky had no timestamp
+pub(crate) fn format_timestamp(secs: Option<usize>, now: u64, tz: TimeZoneChoice) -> String {
+    let secs = match secs {
+        Some(secs) => secs as i64,
+        None => return "unknown".to_string(),
+    };
+    let age = now as i64 - secs;
+    let relative = if age >= 0 {
+        format!("{}s ago", age)
+    } else {
+        // only possible when the local clock is behind OpenSky's
+        format!("in {}s", -age)
+    };
+
+    let time = match tz {
+        TimeZoneChoice::Local => local_time(secs),
+        TimeZoneChoice::Utc => fixed_offset_time(secs, 0),
+        TimeZoneChoice::Offset(offset) => fixed_offset_time(secs, offset),
+    };
+
+    format!("{} ({})", time, relative)
+}
+
+#[cfg(feature = "chrono")]
+fn local_time(secs: i64) -> String {
+    use chrono::{Local, TimeZone};
+
+    match Local.timestamp_opt(secs, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+        None => fixed_offset_time(secs, 0),
+    }
+}
+
+// TimeZoneChoice::Local can't be chosen without chrono, but fall back to UTC regardless
+#[cfg(not(feature = "chrono"))]
+fn local_time(secs: i64) -> String {
+    fixed_offset_time(secs, 0)
+}
+
+fn fixed_offset_time(secs: i64, offset: i32) -> String {
+    let shifted = secs + offset as i64;
+    let (year, month, day) = civil_from_days(shifted.div_euclid(86400));
+    let seconds_of_day = shifted.rem_euclid(86400);
+
+    let zone = if offset == 0 {
+        "UTC".to_string()
+    } else {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        format!("{}{:02}:{:02}", sign, offset / 3600, offset % 3600 / 60)
+    };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        zone
+    )
+}
+
+// days since 1970-01-01 to a (year, month, day) date, after Howard Hinnant's
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc_timestamp() {
+        assert_eq!(
+            format_timestamp(Some(1595268619), 1595268620, TimeZoneChoice::Utc),
+            "2020-07-20 18:10:19 UTC (1s ago)"
+        );
+        assert_eq!(
+            format_timestamp(Some(951782400), 951782400, TimeZoneChoice::Utc),
+            "2000-02-29 00:00:00 UTC (0s ago)"
+        );
+        assert_eq!(
+            format_timestamp(Some(0), 0, TimeZoneChoice::Utc),
+            "1970-01-01 00:00:00 UTC (0s ago)"
+        );
+        assert_eq!(
+            format_timestamp(None, 1595268620, TimeZoneChoice::Utc),
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn test_format_offset_timestamp() {
+        let tz = TimeZoneChoice::from_name("-03:30").unwrap();
+        assert_eq!(tz, TimeZoneChoice::Offset(-12600));
+        assert_eq!(
+            format_timestamp(Some(1595268619), 1595268679, tz),
+            "2020-07-20 14:40:19 -03:30 (60s ago)"
+        );
+
+        let tz = TimeZoneChoice::from_name("+12").unwrap();
+        assert_eq!(
+            format_timestamp(Some(1595268619), 1595268600, tz),
+            "2020-07-21 06:10:19 +12:00 (in 19s)"
+        );
+    }
+
+    #[test]
+    fn test_parse_time_zone() {
+        assert_eq!(TimeZoneChoice::from_name("UTC"), Ok(TimeZoneChoice::Utc));
+        assert_eq!(
+            TimeZoneChoice::from_name("+0545"),
+            Ok(TimeZoneChoice::Offset(20700))
+        );
+        assert!(TimeZoneChoice::from_name("Europe/Dublin").is_err());
+        assert!(TimeZoneChoice::from_name("+25:00").is_err());
+        assert!(TimeZoneChoice::from_name("+01:75").is_err());
+        assert_eq!(
+            TimeZoneChoice::from_name("local").is_ok(),
+            cfg!(feature = "chrono")
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+// serve the bundled sample response once and hand back the request line we received
+fn serve_fixture_once(listener: TcpListener, sender: mpsc::Sender<String>) {
+    let body = fs::read("test/opensky_states_all.json").expect("Can't read sample file");
+    let (mut stream, _) = listener.accept().expect("Mock server accept failed");
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+
+    // drain the remaining headers before replying
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+        line.clear();
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(&body).unwrap();
+
+    sender.send(request_line).unwrap();
+}
+
+struct Run {
+    request_line: String,
+    success: bool,
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+// run the binary against a one-shot mock server, feeding it coordinates on stdin
+fn run_against_fixture(args: &[&str], coords: &str) -> Run {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::channel();
+    let server = thread::spawn(move || serve_fixture_once(listener, sender));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nearest_airplane"))
+        .args(args)
+        .env(
+            "OPENSKY_URL",
+            format!("http://127.0.0.1:{}/api/states/all", port),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start nearest_airplane");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(coords.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    server.join().unwrap();
+
+    Run {
+        request_line: receiver.recv().unwrap(),
+        success: output.status.success(),
+        code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+#[test]
+fn test_nearest_plane_from_mock_server() {
+    let run = run_against_fixture(&[], "53.21 N\n6.18 W");
+
+    assert!(run.request_line.starts_with("GET /api/states/all "));
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(run
+        .stderr
+        .contains("Plane states with known coordinates: 4927"));
+    assert!(
+        run.stdout.contains("icao24: \"4d220a\""),
+        "stdout: {}",
+        run.stdout
+    );
+}
+
+#[test]
+fn test_no_results_message() {
+    let run = run_against_fixture(
+        &["--country", "Ireland", "--exclude-country", "ireland"],
+        "53.21 N\n6.18 W",
+    );
+
+    assert_eq!(run.code, Some(3));
+    assert!(
+        run.stderr.contains("No planes matched"),
+        "stderr: {}",
+        run.stderr
+    );
+    assert!(!run.stderr.contains("panicked"));
+}
+
+#[test]
+fn test_empty_ok() {
+    // the same message, but an empty sky is no longer a failure
+    let run = run_against_fixture(
+        &[
+            "--country",
+            "Ireland",
+            "--exclude-country",
+            "ireland",
+            "--empty-ok",
+        ],
+        "53.21 N\n6.18 W",
+    );
+
+    assert_eq!(run.code, Some(0));
+    assert!(
+        run.stderr.contains("No planes matched"),
+        "stderr: {}",
+        run.stderr
+    );
+}
+
+#[test]
+fn test_any_false_is_silent() {
+    // nothing survives these filters, so nothing is within the radius either
+    let filters = ["--country", "Ireland", "--exclude-country", "ireland"];
+    let mut extras = vec![&[][..], &["--empty-ok"]];
+    if cfg!(feature = "formats") {
+        extras.push(&["--json"]);
+    }
+    for extra in extras {
+        let mut args = vec!["--any", "--radius", "5"];
+        args.extend(filters);
+        args.extend(extra);
+        let run = run_against_fixture(&args, "53.21 N\n6.18 W");
+
+        assert_eq!(run.code, Some(1), "{:?}: {}", extra, run.stderr);
+        assert_eq!(run.stdout, "false\n", "{:?}", extra);
+        assert_eq!(run.stderr, "", "{:?}", extra);
+    }
+}
+
+#[test]
+#[cfg(feature = "formats")]
+fn test_stdin_json_errors() {
+    // asking for JSON in the request is as good as --json on the command line
+    let request = r#"{"lat": 53.21, "lon": -6.18, "json": true,
+        "country": "Ireland", "exclude_country": "ireland"}"#;
+    let run = run_against_fixture(&["--stdin-json"], request);
+
+    assert_eq!(run.code, Some(3));
+    let json: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(json["kind"], "no_results");
+    assert!(!run.stderr.contains("No planes matched"));
+}
+
+#[test]
+fn test_bbox_query() {
+    let run = run_against_fixture(&["--bbox", "52.5,-7,54.5,-5"], "");
+
+    assert!(run.success, "stderr: {}", run.stderr);
+    assert!(
+        run.request_line
+            .starts_with("GET /api/states/all?lamin=52.5&lomin=-7&lamax=54.5&lomax=-5 "),
+        "request: {}",
+        run.request_line
+    );
+}